@@ -0,0 +1,122 @@
+//! Write release information back to Shortcut.
+//!
+//! The changes configured in the `[apply]` section of the configuration are first rendered with
+//! the release's version and name, then compared with each story to build a list of
+//! [`StoryUpdate`]s, which is displayed before being sent to Shortcut.
+//!
+//! The `[release_entity]` section describes a Shortcut epic or story representing the release
+//! itself, which is created or updated with the rendered release notes.
+use ansi_term::{
+    Colour::{Blue, Green, Red},
+    Style,
+};
+use anyhow::{anyhow, Result};
+use minijinja::{context, Environment, UndefinedBehavior};
+
+use crate::{
+    config::{ApplyConfig, ReleaseEntityConfig},
+    shortcut::{ReleaseEntity, StoryUpdate},
+    types::CustomFieldValue,
+};
+
+/// Renders configuration values, which are templates receiving the `version` and `name` of the
+/// release. Undefined variables (e.g. a missing `--version`) are reported as errors rather than
+/// rendered as empty strings.
+pub struct ConfigRenderer<'a> {
+    environment: Environment<'static>,
+    version: Option<&'a str>,
+    name: Option<&'a str>,
+}
+
+impl<'a> ConfigRenderer<'a> {
+    pub fn new(version: Option<&'a str>, name: Option<&'a str>) -> Self {
+        let mut environment = Environment::new();
+        environment.set_undefined_behavior(UndefinedBehavior::Strict);
+        Self {
+            environment,
+            version,
+            name,
+        }
+    }
+
+    pub fn render(&self, value: &str) -> Result<String> {
+        let (version, name) = (self.version, self.name);
+        self.environment
+            .render_str(value, context! { version, name })
+            .map_err(|err| anyhow!("Could not render {:?}: {}", value, err))
+    }
+}
+
+/// Changes to apply to each story, rendered from an [`ApplyConfig`]
+#[derive(Debug, Default)]
+pub struct StoryChanges {
+    pub label: Option<String>,
+    pub workflow_state: Option<String>,
+    pub custom_field: Option<CustomFieldValue>,
+}
+
+impl StoryChanges {
+    pub fn render(config: &ApplyConfig, renderer: &ConfigRenderer) -> Result<Self> {
+        let render = |value: &str| renderer.render(value);
+        Ok(Self {
+            label: config.label.as_deref().map(render).transpose()?,
+            workflow_state: config.workflow_state.as_deref().map(render).transpose()?,
+            custom_field: config
+                .custom_field
+                .as_ref()
+                .map(|custom_field| {
+                    Ok::<_, anyhow::Error>(CustomFieldValue {
+                        name: render(custom_field.name.as_str())?,
+                        value: render(custom_field.value.as_str())?,
+                    })
+                })
+                .transpose()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.label.is_none() && self.workflow_state.is_none() && self.custom_field.is_none()
+    }
+}
+
+impl ReleaseEntity {
+    /// Render the configured release entity. The version is required, as it identifies the
+    /// entity across runs.
+    pub fn render(config: &ReleaseEntityConfig, renderer: &ConfigRenderer) -> Result<Self> {
+        let version = renderer
+            .version
+            .ok_or_else(|| anyhow!("A version is required to publish the release entity"))?;
+        Ok(Self {
+            kind: config.kind,
+            name: renderer.render(&config.name)?,
+            external_id: format!("shortcut_release_helper:{}", version),
+            workflow_state: config.workflow_state.clone(),
+        })
+    }
+}
+
+/// Display the changes which are about to be made to each story
+pub fn print_story_updates(updates: &[StoryUpdate]) {
+    let header_style = Style::new().bold();
+    eprintln!(
+        "\n{}: {}",
+        header_style.paint("Stories to update"),
+        Green.paint(updates.len().to_string())
+    );
+    for update in updates {
+        eprintln!(
+            "{} {}",
+            Blue.paint(format!("sc-{}", update.story_id)),
+            update.story_name
+        );
+        for change in &update.changes {
+            eprintln!(
+                "  {} {}: {} -> {}",
+                Red.paint("~"),
+                change.field,
+                change.before.as_deref().unwrap_or("(none)"),
+                Green.paint(&change.after)
+            );
+        }
+    }
+}
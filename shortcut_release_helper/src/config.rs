@@ -1,5 +1,6 @@
 use std::{
     collections::HashMap,
+    fmt,
     fs::File,
     io::Read,
     path::{Path, PathBuf},
@@ -8,12 +9,103 @@ use std::{
 use anyhow::Result;
 use serde::Deserialize;
 
-use crate::types::{RepositoryConfiguration, RepositoryName};
+use crate::{
+    filter::FilterExpression,
+    output::WriteMode,
+    template::EscapeMode,
+    types::{CustomFieldValue, RepositoryConfiguration, RepositoryName},
+};
 
 #[derive(Debug, PartialEq, Eq, Deserialize)]
 pub struct AppConfig {
-    pub template_file: PathBuf,
+    /// Template rendered to the output file given on the command line
+    pub template_file: Option<PathBuf>,
     pub repositories: HashMap<RepositoryName, RepositoryConfiguration>,
+    /// Filter expression on the stories of the release, overridden by `--filter`
+    pub filter: Option<FilterExpression>,
+    /// Changes made to the stories of the release with `--apply`
+    pub apply: Option<ApplyConfig>,
+    /// Shortcut entity representing the release, created or updated with `--publish`
+    pub release_entity: Option<ReleaseEntityConfig>,
+    /// Directory of the templates included, imported or extended by the templates, defaults to
+    /// the directory of each template
+    pub template_dir: Option<PathBuf>,
+    /// Emoji of the stories and epics in the templates
+    #[serde(default)]
+    pub emoji: EmojiConfig,
+    /// Human-readable names of the story types in the templates, by story type
+    #[serde(default)]
+    pub story_type_labels: HashMap<String, String>,
+    /// Additional release notes files, all rendered from the same release
+    #[serde(default)]
+    pub outputs: Vec<OutputConfig>,
+    /// Marker starting the release note in story comments, used with `--release-notes`
+    #[serde(default = "default_release_note_marker")]
+    pub release_note_marker: String,
+    /// Timezone, locale and format of the dates in the templates
+    #[serde(default)]
+    pub dates: DatesConfig,
+}
+
+fn default_release_note_marker() -> String {
+    "Release note:".to_owned()
+}
+
+/// Emoji returned by the `story_emoji` filter and `epic_emoji` function, on top of the defaults
+#[derive(Debug, Default, PartialEq, Eq, Clone, Deserialize)]
+#[serde(default)]
+pub struct EmojiConfig {
+    /// Emoji by story type, e.g. `feature = ":star:"`
+    pub story_types: HashMap<String, String>,
+    /// Emoji by label name, taking precedence over the story type
+    pub labels: HashMap<String, String>,
+    /// Emoji by epic state (`to do`, `in progress` or `done`)
+    pub epic_states: HashMap<String, String>,
+    /// Emoji of the epics whose state has no emoji
+    pub epic: Option<String>,
+    /// Emoji of the stories whose labels and type have no emoji
+    pub fallback: Option<String>,
+}
+
+/// Settings of the dates rendered by the `today` function and the `format_date` filter
+#[derive(Debug, Default, PartialEq, Eq, Clone, Deserialize)]
+#[serde(default)]
+pub struct DatesConfig {
+    /// IANA name of the timezone, e.g. `Europe/Paris`, defaults to UTC
+    pub timezone: Option<String>,
+    /// Locale of the month and day names, e.g. `fr_FR`, defaults to English
+    pub locale: Option<String>,
+    /// Format of the dates following strftime, e.g. `%-d %B %Y`, defaults to `%F` (`YYYY-MM-DD`)
+    pub format: Option<String>,
+}
+
+/// A release notes file to render
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+pub struct OutputConfig {
+    /// Template file
+    pub template: PathBuf,
+    /// Destination path of the rendered release notes
+    pub path: PathBuf,
+    /// Filter expression on the stories of the release, applied on top of the release filter
+    pub filter: Option<FilterExpression>,
+    /// Auto-escape mode of the template, defaults to the mode matching the extension of the
+    /// destination path, then of the template
+    pub escape: Option<EscapeMode>,
+    /// How the release notes are written to the destination path, overwriting it by default
+    #[serde(default)]
+    pub mode: WriteMode,
+}
+
+/// Changes to write back to each story of the release. All values are templates, receiving the
+/// `version` and `name` of the release.
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+pub struct ApplyConfig {
+    /// Label to add to the story, e.g. `released-{{ version }}`
+    pub label: Option<String>,
+    /// Name of the workflow state to move the story to
+    pub workflow_state: Option<String>,
+    /// Custom field to set on the story
+    pub custom_field: Option<CustomFieldValue>,
 }
 
 impl AppConfig {
@@ -24,3 +116,31 @@ impl AppConfig {
         Ok(toml::from_str(&contents)?)
     }
 }
+
+/// A Shortcut entity representing the release, identified by the release version
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+pub struct ReleaseEntityConfig {
+    pub kind: ReleaseEntityKind,
+    /// Name of the entity, a template receiving the `version` and `name` of the release
+    pub name: String,
+    /// Name of the workflow state of the story, only used when creating a story
+    pub workflow_state: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseEntityKind {
+    /// An epic, sharing a label named after it with all the stories of the release
+    Epic,
+    /// A story, linked to all the stories of the release
+    Story,
+}
+
+impl fmt::Display for ReleaseEntityKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Epic => write!(f, "epic"),
+            Self::Story => write!(f, "story"),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Export of the complete release as structured data (JSON or YAML), for tools consuming the
+//! release without any template.
+use anyhow::Result;
+use clap::ValueEnum;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Metadata, Schema, SchemaObject},
+    schema_for, JsonSchema,
+};
+use serde::Serialize;
+
+use crate::Release;
+
+/// Version of the exported data schema, incremented on every breaking change (removed or renamed
+/// fields, changed types)
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Format of the output file given on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Release notes rendered from the `template_file` of the configuration
+    Template,
+    /// The complete release as JSON
+    Json,
+    /// The complete release as YAML
+    Yaml,
+}
+
+/// Any entity returned by the Shortcut API (story, epic, iteration, ...), exported as-is
+pub struct ShortcutEntity;
+
+impl JsonSchema for ShortcutEntity {
+    fn schema_name() -> String {
+        "ShortcutEntity".to_owned()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "Entity of the Shortcut API, see https://developer.shortcut.com/api/rest/v3"
+                        .to_owned(),
+                ),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// The exported release, along with the version of its schema
+#[derive(Serialize, JsonSchema)]
+#[schemars(title = "Release")]
+struct ReleaseExport<'a> {
+    /// Version of the schema of the exported release
+    schema_version: u32,
+    #[serde(flatten)]
+    release: &'a Release<'a>,
+}
+
+/// Serialize the release in the given format, `None` for [`OutputFormat::Template`]
+pub fn export(release: &Release, format: OutputFormat) -> Result<Option<String>> {
+    let release_export = ReleaseExport {
+        schema_version: SCHEMA_VERSION,
+        release,
+    };
+    Ok(match format {
+        OutputFormat::Template => None,
+        OutputFormat::Json => Some(serde_json::to_string_pretty(&release_export)? + "\n"),
+        OutputFormat::Yaml => Some(serde_yaml::to_string(&release_export)?),
+    })
+}
+
+/// JSON Schema of the exported release
+pub fn json_schema() -> Result<String> {
+    Ok(serde_json::to_string_pretty(&schema_for!(ReleaseExport<'static>))? + "\n")
+}
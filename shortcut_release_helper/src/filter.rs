@@ -0,0 +1,528 @@
+//! Story filter expressions.
+//!
+//! A filter expression combines predicates on stories with `and`, `or`, `not` and parentheses,
+//! e.g. `label:"Release Notes" or (type:bug and not label:internal) and epic:123 and state:done`.
+//! `not` has the highest precedence, followed by `and`, then `or`.
+//!
+//! Each predicate has the form `<key>:<value>`, where the value is either a single word or a
+//! double-quoted string (use `\"` for a literal double quote). The supported keys are:
+//!
+//! - `label`: the story has a label with this name
+//! - `type`: the story type (`feature`, `bug` or `chore`)
+//! - `epic`: the id of the epic of the story, or `none` for stories without an epic
+//! - `state`: the workflow state type (`unstarted`, `started` or `done`) or name of the story
+//! - `id`: the story id
+//! - `field`: a custom field value, as `<field name>=<value>`
+//!
+//! Text comparisons are case-insensitive.
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use serde::Deserialize;
+use shortcut_client::models::Story;
+
+use crate::{
+    shortcut::{CustomFields, StoryState},
+    types::CustomFieldValue,
+};
+
+/// Data needed to evaluate filter expressions, besides the story itself
+pub struct FilterContext<'a> {
+    pub custom_fields: &'a CustomFields,
+    pub workflow_states: &'a HashMap<i64, StoryState>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum FilterExpression {
+    Predicate(Predicate),
+    Not(Box<FilterExpression>),
+    And(Box<FilterExpression>, Box<FilterExpression>),
+    Or(Box<FilterExpression>, Box<FilterExpression>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Predicate {
+    Label(String),
+    Type(String),
+    Epic(Option<i64>),
+    State(String),
+    Id(i64),
+    CustomField(CustomFieldValue),
+}
+
+impl FilterExpression {
+    pub fn matches(&self, story: &Story, context: &FilterContext) -> bool {
+        match self {
+            Self::Predicate(predicate) => predicate.matches(story, context),
+            Self::Not(expression) => !expression.matches(story, context),
+            Self::And(left, right) => left.matches(story, context) && right.matches(story, context),
+            Self::Or(left, right) => left.matches(story, context) || right.matches(story, context),
+        }
+    }
+}
+
+impl Predicate {
+    fn matches(&self, story: &Story, context: &FilterContext) -> bool {
+        match self {
+            Self::Label(name) => story
+                .labels
+                .iter()
+                .any(|label| label.name.eq_ignore_ascii_case(name)),
+            Self::Type(story_type) => story.story_type.eq_ignore_ascii_case(story_type),
+            Self::Epic(epic_id) => story.epic_id == *epic_id,
+            Self::State(state) => context
+                .workflow_states
+                .get(&story.workflow_state_id)
+                .map_or(false, |workflow_state| {
+                    workflow_state.state_type.eq_ignore_ascii_case(state)
+                        || workflow_state.name.eq_ignore_ascii_case(state)
+                }),
+            Self::Id(id) => story.id == *id,
+            Self::CustomField(custom_field) => {
+                custom_field.is_in(&context.custom_fields.story_values(story))
+            }
+        }
+    }
+}
+
+/// Error while parsing a filter expression, pointing at the offending part of the expression
+#[derive(Debug, PartialEq, Eq)]
+pub struct FilterParseError {
+    expression: String,
+    /// Byte offset of the error in the expression
+    position: usize,
+    message: String,
+}
+
+impl fmt::Display for FilterParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let column = self.expression[..self.position].chars().count();
+        writeln!(
+            f,
+            "invalid filter, {} at column {}",
+            self.message,
+            column + 1
+        )?;
+        writeln!(f, "  {}", self.expression)?;
+        write!(f, "  {:column$}^", "")
+    }
+}
+
+impl std::error::Error for FilterParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    OpenParen,
+    CloseParen,
+    And,
+    Or,
+    Not,
+    Predicate { key: String, value: String },
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OpenParen => write!(f, "'('"),
+            Self::CloseParen => write!(f, "')'"),
+            Self::And => write!(f, "'and'"),
+            Self::Or => write!(f, "'or'"),
+            Self::Not => write!(f, "'not'"),
+            Self::Predicate { key, .. } => write!(f, "'{}:'", key),
+        }
+    }
+}
+
+struct Parser<'a> {
+    expression: &'a str,
+    /// Tokens along with their byte offset in the expression
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(expression: &'a str) -> Result<Self, FilterParseError> {
+        let mut parser = Self {
+            expression,
+            tokens: Vec::new(),
+            pos: 0,
+        };
+        parser.tokenize()?;
+        Ok(parser)
+    }
+
+    fn error(&self, position: usize, message: impl Into<String>) -> FilterParseError {
+        FilterParseError {
+            expression: self.expression.to_owned(),
+            position,
+            message: message.into(),
+        }
+    }
+
+    fn tokenize(&mut self) -> Result<(), FilterParseError> {
+        let expression = self.expression;
+        let mut chars = expression.char_indices().peekable();
+        while let Some(&(start, c)) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c == '(' {
+                chars.next();
+                self.tokens.push((start, Token::OpenParen));
+            } else if c == ')' {
+                chars.next();
+                self.tokens.push((start, Token::CloseParen));
+            } else if c.is_ascii_alphabetic() {
+                let mut word = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        word.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                if matches!(chars.peek(), Some((_, ':'))) {
+                    let (value_start, _) = chars.next().expect("Should be a colon");
+                    let value = match chars.peek() {
+                        Some(&(quote_start, '"')) => {
+                            chars.next();
+                            let mut value = String::new();
+                            loop {
+                                match chars.next() {
+                                    Some((_, '"')) => break,
+                                    Some((_, '\\')) => match chars.next() {
+                                        Some((_, c)) => value.push(c),
+                                        None => {
+                                            return Err(self
+                                                .error(quote_start, "unterminated quoted value"))
+                                        }
+                                    },
+                                    Some((_, c)) => value.push(c),
+                                    None => {
+                                        return Err(
+                                            self.error(quote_start, "unterminated quoted value")
+                                        )
+                                    }
+                                }
+                            }
+                            value
+                        }
+                        _ => {
+                            let mut value = String::new();
+                            while let Some(&(_, c)) = chars.peek() {
+                                if c.is_whitespace() || c == '(' || c == ')' {
+                                    break;
+                                }
+                                value.push(c);
+                                chars.next();
+                            }
+                            value
+                        }
+                    };
+                    if value.is_empty() {
+                        return Err(
+                            self.error(value_start + 1, format!("missing value for '{}:'", word))
+                        );
+                    }
+                    self.tokens
+                        .push((start, Token::Predicate { key: word, value }));
+                } else {
+                    let token = match word.to_ascii_lowercase().as_str() {
+                        "and" => Token::And,
+                        "or" => Token::Or,
+                        "not" => Token::Not,
+                        _ => {
+                            return Err(self.error(
+                                start,
+                                format!(
+                                    "unexpected word '{}', expected 'and', 'or', 'not' or a \
+                                     '<key>:<value>' predicate",
+                                    word
+                                ),
+                            ))
+                        }
+                    };
+                    self.tokens.push((start, token));
+                }
+            } else {
+                return Err(self.error(start, format!("unexpected character '{}'", c)));
+            }
+        }
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    /// Position of the current token, or the end of the expression
+    fn current_position(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.expression.len(), |(position, _)| *position)
+    }
+
+    fn parse(mut self) -> Result<FilterExpression, FilterParseError> {
+        if self.tokens.is_empty() {
+            return Err(self.error(0, "empty expression"));
+        }
+        let expression = self.parse_or()?;
+        if let Some(token) = self.peek() {
+            return Err(self.error(
+                self.current_position(),
+                format!("unexpected {}, expected 'and' or 'or'", token),
+            ));
+        }
+        Ok(expression)
+    }
+
+    fn parse_or(&mut self) -> Result<FilterExpression, FilterParseError> {
+        let mut expression = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let right = self.parse_and()?;
+            expression = FilterExpression::Or(Box::new(expression), Box::new(right));
+        }
+        Ok(expression)
+    }
+
+    fn parse_and(&mut self) -> Result<FilterExpression, FilterParseError> {
+        let mut expression = self.parse_not()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            let right = self.parse_not()?;
+            expression = FilterExpression::And(Box::new(expression), Box::new(right));
+        }
+        Ok(expression)
+    }
+
+    fn parse_not(&mut self) -> Result<FilterExpression, FilterParseError> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            let expression = self.parse_not()?;
+            Ok(FilterExpression::Not(Box::new(expression)))
+        } else {
+            self.parse_primary()
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<FilterExpression, FilterParseError> {
+        let position = self.current_position();
+        match self.tokens.get(self.pos).map(|(_, token)| token.clone()) {
+            Some(Token::OpenParen) => {
+                self.pos += 1;
+                let expression = self.parse_or()?;
+                if self.peek() == Some(&Token::CloseParen) {
+                    self.pos += 1;
+                    Ok(expression)
+                } else {
+                    Err(self.error(
+                        self.current_position(),
+                        "expected ')' to close the parenthesis",
+                    ))
+                }
+            }
+            Some(Token::Predicate { key, value }) => {
+                self.pos += 1;
+                let predicate = self.parse_predicate(position, &key, value)?;
+                Ok(FilterExpression::Predicate(predicate))
+            }
+            Some(token) => Err(self.error(
+                position,
+                format!("unexpected {}, expected a predicate or '('", token),
+            )),
+            None => Err(self.error(position, "unexpected end of expression")),
+        }
+    }
+
+    fn parse_predicate(
+        &self,
+        position: usize,
+        key: &str,
+        value: String,
+    ) -> Result<Predicate, FilterParseError> {
+        let parse_id = |value: &str| {
+            value.parse::<i64>().map_err(|_| {
+                self.error(
+                    position,
+                    format!("expected a numeric id for '{}:', got '{}'", key, value),
+                )
+            })
+        };
+        let predicate = match key.to_ascii_lowercase().as_str() {
+            "label" => Predicate::Label(value),
+            "type" => Predicate::Type(value),
+            "epic" if value.eq_ignore_ascii_case("none") => Predicate::Epic(None),
+            "epic" => Predicate::Epic(Some(parse_id(&value)?)),
+            "state" => Predicate::State(value),
+            "id" => Predicate::Id(parse_id(value.trim_start_matches("sc-"))?),
+            "field" => Predicate::CustomField(
+                CustomFieldValue::from_str(&value)
+                    .map_err(|err| self.error(position, err.to_string()))?,
+            ),
+            _ => {
+                return Err(self.error(
+                    position,
+                    format!(
+                        "unknown key '{}', expected one of label, type, epic, state, id, field",
+                        key
+                    ),
+                ))
+            }
+        };
+        Ok(predicate)
+    }
+}
+
+impl FromStr for FilterExpression {
+    type Err = FilterParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parser::new(s)?.parse()
+    }
+}
+
+impl TryFrom<String> for FilterExpression {
+    type Error = FilterParseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(expression: &str) -> FilterExpression {
+        expression.parse().expect("Should be a valid expression")
+    }
+
+    fn parse_error(expression: &str) -> String {
+        expression
+            .parse::<FilterExpression>()
+            .expect_err("Should be an invalid expression")
+            .to_string()
+    }
+
+    fn label(name: &str) -> Box<FilterExpression> {
+        Box::new(FilterExpression::Predicate(Predicate::Label(
+            name.to_owned(),
+        )))
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_which_binds_tighter_than_or() {
+        assert_eq!(
+            parse("label:a or label:b and not label:c"),
+            FilterExpression::Or(
+                label("a"),
+                Box::new(FilterExpression::And(
+                    label("b"),
+                    Box::new(FilterExpression::Not(label("c")))
+                ))
+            )
+        );
+        assert_eq!(
+            parse("(label:a OR label:b) and label:c"),
+            FilterExpression::And(
+                Box::new(FilterExpression::Or(label("a"), label("b"))),
+                label("c")
+            )
+        );
+    }
+
+    #[test]
+    fn quoted_values_may_contain_escaped_quotes() {
+        assert_eq!(parse(r#"label:"Release Notes""#), *label("Release Notes"));
+        assert_eq!(parse(r#"label:"say \"hi\"""#), *label(r#"say "hi""#));
+    }
+
+    #[test]
+    fn parses_epic_and_id_predicates() {
+        assert_eq!(
+            parse("epic:none"),
+            FilterExpression::Predicate(Predicate::Epic(None))
+        );
+        assert_eq!(
+            parse("epic:42"),
+            FilterExpression::Predicate(Predicate::Epic(Some(42)))
+        );
+        assert_eq!(
+            parse("id:sc-123"),
+            FilterExpression::Predicate(Predicate::Id(123))
+        );
+        assert_eq!(
+            parse("id:123"),
+            FilterExpression::Predicate(Predicate::Id(123))
+        );
+        assert_eq!(
+            parse(r#"field:"Product area=Billing""#),
+            FilterExpression::Predicate(Predicate::CustomField(CustomFieldValue {
+                name: "Product area".to_owned(),
+                value: "Billing".to_owned(),
+            }))
+        );
+    }
+
+    #[test]
+    fn reports_unterminated_quoted_values() {
+        assert_eq!(
+            parse_error(r#"type:bug or label:"Release Notes"#),
+            concat!(
+                "invalid filter, unterminated quoted value at column 19\n",
+                "  type:bug or label:\"Release Notes\n",
+                "                    ^"
+            )
+        );
+    }
+
+    #[test]
+    fn reports_missing_closing_parenthesis() {
+        assert_eq!(
+            parse_error("(type:bug or type:chore"),
+            concat!(
+                "invalid filter, expected ')' to close the parenthesis at column 24\n",
+                "  (type:bug or type:chore\n",
+                "                         ^"
+            )
+        );
+    }
+
+    #[test]
+    fn reports_unknown_keys() {
+        assert_eq!(
+            parse_error("type:bug and owner:ada"),
+            concat!(
+                "invalid filter, unknown key 'owner', expected one of label, type, epic, state, ",
+                "id, field at column 14\n",
+                "  type:bug and owner:ada\n",
+                "               ^"
+            )
+        );
+    }
+
+    #[test]
+    fn reports_empty_expressions() {
+        assert_eq!(
+            parse_error("  "),
+            concat!(
+                "invalid filter, empty expression at column 1\n",
+                "    \n",
+                "  ^"
+            )
+        );
+    }
+
+    #[test]
+    fn compares_custom_field_values_case_insensitively() {
+        let custom_field = CustomFieldValue {
+            name: "product area".to_owned(),
+            value: "BILLING".to_owned(),
+        };
+        let story_values = [("Product area".to_owned(), "Billing".to_owned())].into();
+        assert!(custom_field.is_in(&story_values));
+        let other_value = [("Product area".to_owned(), "Payments".to_owned())].into();
+        assert!(!custom_field.is_in(&other_value));
+    }
+}
@@ -2,6 +2,7 @@
 //!
 //! The `Repository` structures wraps a [`git2::Repository`].
 use anyhow::Result;
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
 use git2::{
     Commit as GitCommit, ErrorClass as GitErrorClass, ErrorCode as GitErrorCode, Oid as GitOid,
     Repository as GitRepository,
@@ -58,6 +59,7 @@ impl<'a> Repository<'a> {
                     Some(UnreleasedCommit {
                         id: commit.id(),
                         message: commit.message().map(|msg| msg.to_owned()),
+                        committed_at: commit_time(&commit),
                     })
                 } else {
                     None
@@ -68,11 +70,19 @@ impl<'a> Repository<'a> {
             next_head: HeadCommit {
                 id: next_head.id(),
                 message: next_head.message().map(|msg| msg.to_owned()),
+                committed_at: commit_time(&next_head),
             },
             unreleased_commits,
         })
     }
 
+    /// Host and path of the `origin` remote, e.g. `github.com/acme/app` for
+    /// `git@github.com:acme/app.git`, if any
+    pub fn origin_path(&self) -> Option<String> {
+        let remote = self.repository.find_remote("origin").ok()?;
+        remote.url().map(remote_path)
+    }
+
     fn find_commit(&'a self, branch: &RepositoryReference) -> Result<GitCommit<'a>> {
         let maybe_reference = self
             .repository
@@ -98,3 +108,35 @@ impl<'a> Repository<'a> {
         }
     }
 }
+
+/// Host and path of a remote URL, either a URL (e.g. `https://github.com/acme/app.git`) or a
+/// scp-like address (e.g. `git@github.com:acme/app.git`), in lowercase
+fn remote_path(url: &str) -> String {
+    let (location, is_scp_like) = match url.split_once("://") {
+        Some((_scheme, location)) => (location, false),
+        None => (url, true),
+    };
+    let location = location
+        .split_once('@')
+        .map_or(location, |(_user, location)| location);
+    let location = if is_scp_like {
+        location.replacen(':', "/", 1)
+    } else {
+        location.to_owned()
+    };
+    location
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .to_lowercase()
+}
+
+/// Time of a commit, with the UTC offset of the committer
+fn commit_time(commit: &GitCommit) -> DateTime<FixedOffset> {
+    let time = commit.time();
+    let offset = FixedOffset::east_opt(time.offset_minutes() * 60)
+        .unwrap_or_else(|| FixedOffset::east_opt(0).expect("UTC offset should be valid"));
+    Utc.timestamp_opt(time.seconds(), 0)
+        .single()
+        .unwrap_or_default()
+        .with_timezone(&offset)
+}
@@ -28,8 +28,20 @@
 //! dev = { location = "../project1", release_branch = "master", next_branch = "next" }
 //! # Same for the second repository
 //! legacy = { location = "../project2", release_branch = "master", next_branch = "next" }
+//!
+//! # Additional release notes files, rendered from the same release
+//! [[outputs]]
+//! template = "slack.md.jinja"
+//! path = "slack.md"
+//! filter = 'label:"Release Notes"'
 //! ```
 //!
+//! # Environment
+//!
+//! The Shortcut API token must be provided via the `SHORTCUT_TOKEN` environment variable.
+//! `SHORTCUT_API_URL` can be used to send API requests to another server (e.g. a local stand-in
+//! server), instead of `https://api.app.shortcut.com`.
+//!
 //! # Debugging
 //!
 //! You can use `RUST_LOG` to control the amount logged by the utility in the console.
@@ -40,33 +52,47 @@ extern crate derive_more;
 use std::{
     collections::{HashMap, HashSet},
     env::{var, VarError},
-    fs,
-    path::PathBuf,
+    fs, io, mem,
+    path::{Path, PathBuf},
     time::Instant,
 };
 
 use ansi_term::{
-    Colour::{Blue, Green, Red},
+    Colour::{Blue, Green, Red, Yellow},
     Style,
 };
-use anyhow::{anyhow, Result};
-use clap::Parser;
+use anyhow::{anyhow, bail, Result};
+use apply::{ConfigRenderer, StoryChanges};
+use clap::{Parser, Subcommand};
 use git::{Repository, UnreleasedCommits};
 use itertools::Itertools;
+use schemars::JsonSchema;
 use serde::Serialize;
-use shortcut::{ReleaseContent, StoryId};
-use shortcut_client::models::{Epic, Story};
+use shortcut::{
+    member_name, MemberIdToMember, ReleaseContent, ReleaseEntity, ReleaseEpic, ReleaseStory,
+    StoryId,
+};
+use shortcut_client::models::{Group, Iteration, Objective};
 use tracing::{debug, info};
 use types::{RepoToCommits, RepoToHeadCommit};
 
 use crate::{
-    config::AppConfig,
-    shortcut::{parse_commits, ShortcutClient, StoryLabelFilter},
-    types::{RepositoryConfiguration, RepositoryName, ShortcutApiKey},
+    config::{AppConfig, OutputConfig},
+    export::{OutputFormat, ShortcutEntity},
+    filter::{FilterContext, FilterExpression},
+    output::WriteMode,
+    shortcut::{parse_commits, ReleaseOptions, ShortcutClient, StoryFilter},
+    template::{EscapeMode, FileTemplate, TemplateOptions},
+    types::{CustomFieldValue, RepositoryConfiguration, RepositoryName, ShortcutApiKey},
 };
 
+mod apply;
 mod config;
+mod export;
+mod filter;
 mod git;
+mod output;
+mod preview;
 mod shortcut;
 mod template;
 mod types;
@@ -75,8 +101,24 @@ mod types;
 #[derive(Parser, Debug)]
 #[clap(author, about, long_about = None, disable_version_flag = true)]
 struct Args {
-    /// Output file for the release notes
-    output_file: PathBuf,
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// Output file for the release notes, rendered from the `template_file` of the configuration
+    /// unless another --format is given, `-` for the standard output
+    output_file: Option<PathBuf>,
+    /// Template of the output file, `-` to read it from the standard input - overrides the
+    /// `template_file` of the configuration
+    #[clap(long, requires = "output_file")]
+    template: Option<PathBuf>,
+    /// Format of the output file: release notes rendered from the template, or the complete
+    /// release as JSON or YAML
+    #[clap(long, value_enum, default_value_t = OutputFormat::Template, requires = "output_file")]
+    format: OutputFormat,
+    /// How the release notes are written to the output file: overwrite it, insert the release
+    /// at the start (below the `<!-- releases -->` marker) or at the end of the file, or replace
+    /// the section of the same version
+    #[clap(long, value_enum, default_value_t = WriteMode::Overwrite, requires = "output_file")]
+    mode: WriteMode,
     /// Version to release
     #[clap(long)]
     version: Option<String>,
@@ -96,16 +138,154 @@ struct Args {
     /// Label of story to include, can be used multiple times
     #[clap(long)]
     include_story_label: Vec<String>,
+    /// Custom field value of story to exclude, as `<field name>=<value>`, can be used multiple
+    /// times - has priority over include-custom-field
+    #[clap(long)]
+    exclude_custom_field: Vec<CustomFieldValue>,
+    /// Custom field value of story to include, as `<field name>=<value>`, can be used multiple
+    /// times
+    #[clap(long)]
+    include_custom_field: Vec<CustomFieldValue>,
+    /// Filter expression on stories, e.g. `label:"Release Notes" or (type:bug and not
+    /// label:internal)` - overrides the filter of the configuration file
+    #[clap(long)]
+    filter: Option<FilterExpression>,
     /// Exclude unparsed commits
     #[clap(long)]
     exclude_unparsed_commits: bool,
+    /// Retrieve the iterations of the stories
+    #[clap(long)]
+    include_iterations: bool,
+    /// Retrieve the groups (teams) of the stories
+    #[clap(long)]
+    include_groups: bool,
+    /// Retrieve the objectives (formerly milestones) of the epics
+    #[clap(long)]
+    include_objectives: bool,
+    /// Retrieve the stories of the epics, to list the ones missing from the release
+    #[clap(long)]
+    include_epic_stories: bool,
+    /// Link unparsed commits to stories using the commits, branches and pull requests known to
+    /// Shortcut's VCS integration
+    #[clap(long)]
+    vcs_lookup: bool,
+    /// Shortcut search query for additional stories to link unparsed commits to, implies
+    /// --vcs-lookup
+    #[clap(long)]
+    vcs_lookup_query: Option<String>,
+    /// Retrieve the stories linked to the stories of the release (blocks, duplicates, relates to)
+    #[clap(long)]
+    include_story_links: bool,
+    /// Replace the stories duplicating another story by the latter, listing them in its
+    /// `folded_duplicates`
+    #[clap(long)]
+    fold_duplicates: bool,
+    /// Extract the release note of each story from its comments (see `release_note_marker` in the
+    /// configuration), and list the stories without one in the summary
+    #[clap(long)]
+    release_notes: bool,
+    /// Write the changes of the `[apply]` configuration section back to the stories
+    #[clap(long)]
+    apply: bool,
+    /// Only display the changes --apply would make and the release entity --publish would
+    /// create or update, without sending them to Shortcut
+    #[clap(long)]
+    dry_run: bool,
+    /// Create or update the release entity of the `[release_entity]` configuration section, with
+    /// the release notes of the first output as description (requires --version)
+    #[clap(long)]
+    publish: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write a built-in template to a file, as a starting point for a custom template
+    DumpTemplate {
+        /// Name of the built-in template, e.g. `markdown-by-epic`
+        name: String,
+        /// Output file, defaults to the file name of the built-in template
+        output_file: Option<PathBuf>,
+    },
+    /// Check a template without any git or Shortcut access: report its unknown filters and
+    /// functions, and render it against a synthetic release, reporting undefined variables
+    CheckTemplate {
+        /// Template to check, `builtin:<name>` or `-` for the standard input, defaults to the
+        /// `template_file` of the configuration
+        template: Option<PathBuf>,
+    },
+    /// Retrieve the release once, then render the template again whenever it changes, to the
+    /// output file or the terminal
+    Preview {
+        /// Template to preview, defaults to the `template_file` of the configuration
+        template: Option<PathBuf>,
+        /// Output file, defaults to the standard output
+        output_file: Option<PathBuf>,
+    },
+    /// Write the JSON Schema of the release exported by --format json or yaml
+    JsonSchema {
+        /// Output file, defaults to the standard output
+        output_file: Option<PathBuf>,
+    },
+}
+
+/// Write a built-in template to a file
+fn dump_template(name: &str, output_file: Option<&Path>) -> Result<()> {
+    let builtin_template = template::builtin_template(name)?;
+    let output_file = output_file.unwrap_or_else(|| Path::new(builtin_template.file_name));
+    fs::write(output_file, builtin_template.content)?;
+    println!(
+        "{} {} to {}",
+        Style::new().bold().paint("Wrote template"),
+        builtin_template.name,
+        Blue.paint(output_file.display().to_string())
+    );
+    Ok(())
+}
+
+/// Check a template, printing the synthetic release rendered by the template
+fn check_template(template: Option<&Path>) -> Result<()> {
+    let config_path = PathBuf::from("config.toml");
+    // The configuration is optional, it only provides the template settings
+    let config = config_path
+        .exists()
+        .then(|| AppConfig::parse(&config_path))
+        .transpose()?;
+    let template = template
+        .map(Path::to_path_buf)
+        .or_else(|| config.as_ref()?.template_file.clone())
+        .ok_or_else(|| anyhow!("Please provide a template, or a template_file in config.toml"))?;
+    let template_options = config
+        .as_ref()
+        .map(TemplateOptions::new)
+        .transpose()?
+        .unwrap_or_default();
+    let rendered_sample = FileTemplate::load(&template, &template_options)?
+        .check()
+        .map_err(|err| anyhow!("Template {} is invalid:\n{}", template.display(), err))?;
+    print!("{}", rendered_sample);
+    eprintln!(
+        "\n{} {}",
+        Green.paint("Template is valid:"),
+        Blue.paint(template.display().to_string())
+    );
+    Ok(())
+}
+
+/// Write the JSON Schema of the exported release
+fn dump_json_schema(output_file: Option<&Path>) -> Result<()> {
+    let schema = export::json_schema()?;
+    match output_file {
+        Some(output_file) => fs::write(output_file, schema)?,
+        None => print!("{}", schema),
+    }
+    Ok(())
 }
 
 #[tracing::instrument(level = "info", skip_all, fields(repo = %repo_name))]
 fn find_unreleased_commits(
     repo_name: &RepositoryName,
     repo_config: &RepositoryConfiguration,
-) -> Result<UnreleasedCommits> {
+) -> Result<(UnreleasedCommits, Option<String>)> {
     info!(
         release_branch = %repo_config.release_branch,
         next_branch = %repo_config.next_branch
@@ -130,24 +310,131 @@ fn find_unreleased_commits(
         );
         commits
     };
-    Ok(commits)
+    let origin_path = repo.origin_path();
+    if origin_path.is_none() {
+        debug!("No origin remote, the repository cannot be matched to Shortcut's VCS integration");
+    }
+    Ok((commits, origin_path))
 }
 
-fn print_summary(release: &ReleaseContent) {
+fn print_summary(release: &ReleaseContent, check_release_notes: bool) {
     let header_style = Style::new().bold();
-    println!(
+    eprintln!(
         "{}: {}",
         header_style.paint("Total stories"),
         Green.paint(release.stories.len().to_string())
     );
-    println!(
+    eprintln!(
         "\n{}: {}",
         header_style.paint("Total epics"),
         Green.paint(release.epics.len().to_string())
     );
+    for release_epic in &release.epics {
+        if let Some(stories_not_in_release) = &release_epic.stories_not_in_release {
+            if !stories_not_in_release.unfinished.is_empty() {
+                eprintln!(
+                    "  {} epic {} is only partially shipped, {} unfinished stories: {}",
+                    Yellow.paint("warning:"),
+                    release_epic.epic.name,
+                    Red.paint(stories_not_in_release.unfinished.len().to_string()),
+                    stories_not_in_release
+                        .unfinished
+                        .iter()
+                        .map(|story| format!("sc-{}", story.id))
+                        .join(", ")
+                );
+            }
+            // Done stories without commits in the release, e.g. shipped by a previous release
+            // or requiring no code change, are worth checking but not alarming
+            if !stories_not_in_release.done.is_empty() {
+                eprintln!(
+                    "  {} epic {} has {} done stories outside of the release commits: {}",
+                    Blue.paint("note:"),
+                    release_epic.epic.name,
+                    stories_not_in_release.done.len(),
+                    stories_not_in_release
+                        .done
+                        .iter()
+                        .map(|story| format!("sc-{}", story.id))
+                        .join(", ")
+                );
+            }
+        }
+    }
+    let contributors = release
+        .stories
+        .iter()
+        .flat_map(|release_story| release_story.story.owner_ids.iter())
+        .unique()
+        .map(|owner_id| {
+            release
+                .members
+                .get(owner_id)
+                .map_or(owner_id.as_str(), member_name)
+        })
+        .sorted()
+        .collect::<Vec<_>>();
+    if !contributors.is_empty() {
+        eprintln!(
+            "\n{}: {}",
+            header_style.paint("Contributors"),
+            Green.paint(contributors.join(", "))
+        );
+    }
+    let not_done_stories = release
+        .stories
+        .iter()
+        .filter(|release_story| {
+            !release_story
+                .workflow_state
+                .as_ref()
+                .map_or(false, |state| state.is_done())
+        })
+        .collect::<Vec<_>>();
+    if !not_done_stories.is_empty() {
+        eprintln!(
+            "\n{}: {}",
+            header_style.paint("Stories not in a done state"),
+            Red.paint(not_done_stories.len().to_string())
+        );
+        for release_story in not_done_stories {
+            eprintln!(
+                "  {} sc-{} {} ({})",
+                Yellow.paint("warning:"),
+                release_story.story.id,
+                release_story.story.name,
+                release_story
+                    .workflow_state
+                    .as_ref()
+                    .map_or("unknown state", |state| state.name.as_str())
+            );
+        }
+    }
+    if check_release_notes {
+        let stories_without_release_note = release
+            .stories
+            .iter()
+            .filter(|release_story| release_story.release_note.is_none())
+            .collect::<Vec<_>>();
+        if !stories_without_release_note.is_empty() {
+            eprintln!(
+                "\n{}: {}",
+                header_style.paint("Stories without a release note"),
+                Red.paint(stories_without_release_note.len().to_string())
+            );
+            for release_story in stories_without_release_note {
+                eprintln!(
+                    "  {} sc-{} {}",
+                    Yellow.paint("warning:"),
+                    release_story.story.id,
+                    release_story.story.name
+                );
+            }
+        }
+    }
     for (repo, commits) in &release.unparsed_commits {
         if !commits.is_empty() {
-            println!(
+            eprintln!(
                 "\n{}{}: {}",
                 header_style.paint("Total unparsed commits in "),
                 Blue.paint(repo.as_ref()),
@@ -157,74 +444,329 @@ fn print_summary(release: &ReleaseContent) {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct Release<'a> {
     pub name: Option<&'a str>,
     pub version: Option<&'a str>,
     pub description: Option<&'a str>,
-    pub stories: Vec<Story>,
-    pub epics: Vec<Epic>,
+    pub stories: Vec<ReleaseStory>,
+    pub epics: Vec<ReleaseEpic>,
+    #[schemars(with = "Vec<ShortcutEntity>")]
+    pub iterations: Vec<Iteration>,
+    #[schemars(with = "Vec<ShortcutEntity>")]
+    pub groups: Vec<Group>,
+    #[schemars(with = "Vec<ShortcutEntity>")]
+    pub objectives: Vec<Objective>,
+    #[schemars(with = "HashMap<String, ShortcutEntity>")]
+    pub members: MemberIdToMember,
     pub unparsed_commits: RepoToCommits,
     pub next_heads: RepoToHeadCommit,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let _ = dotenvy::dotenv().ok();
-    tracing_subscriber::fmt::init();
-    let args = Args::parse();
+impl<'a> Release<'a> {
+    /// Release of the command-line arguments, taking the stories, epics and other entities of the
+    /// release content
+    fn new(
+        args: &'a Args,
+        release_content: &mut ReleaseContent,
+        next_heads: RepoToHeadCommit,
+    ) -> Self {
+        let include_unparsed_commits = !args.exclude_unparsed_commits;
+        Self {
+            name: args.name.as_deref(),
+            version: args.version.as_deref(),
+            description: args.description.as_deref(),
+            stories: mem::take(&mut release_content.stories),
+            epics: mem::take(&mut release_content.epics),
+            iterations: mem::take(&mut release_content.iterations),
+            groups: mem::take(&mut release_content.groups),
+            objectives: mem::take(&mut release_content.objectives),
+            members: mem::take(&mut release_content.members),
+            unparsed_commits: include_unparsed_commits
+                .then(|| mem::take(&mut release_content.unparsed_commits))
+                .unwrap_or_default(),
+            next_heads,
+        }
+    }
+
+    /// Copy of the release restricted to the stories matching the filter, and to their epics
+    fn filtered(&self, filter: &FilterExpression, context: &FilterContext) -> Self {
+        let stories = self
+            .stories
+            .iter()
+            .filter(|release_story| filter.matches(&release_story.story, context))
+            .cloned()
+            .collect::<Vec<_>>();
+        let epics = self
+            .epics
+            .iter()
+            .filter(|release_epic| {
+                stories
+                    .iter()
+                    .any(|release_story| release_story.story.epic_id == Some(release_epic.epic.id))
+            })
+            .cloned()
+            .collect();
+        Self {
+            name: self.name,
+            version: self.version,
+            description: self.description,
+            stories,
+            epics,
+            iterations: self.iterations.clone(),
+            groups: self.groups.clone(),
+            objectives: self.objectives.clone(),
+            members: self.members.clone(),
+            unparsed_commits: self.unparsed_commits.clone(),
+            next_heads: self.next_heads.clone(),
+        }
+    }
+}
+
+/// Shortcut client authenticated with the `SHORTCUT_TOKEN` environment variable
+fn shortcut_client() -> Result<ShortcutClient> {
     let api_key = ShortcutApiKey::new(var("SHORTCUT_TOKEN").map_err(|err| match err {
         VarError::NotPresent => anyhow!("Missing SHORTCUT_TOKEN environment variable. Please provide it in a .env file or set it in your environment."),
         VarError::NotUnicode(_) => err.into(),
     })?);
-    let config = AppConfig::parse(&PathBuf::from("config.toml"))?;
-    let template_content = fs::read_to_string(&config.template_file)?;
-    let template = template::FileTemplate::new(&template_content)?;
-    let repo_names_and_heads_and_commits = futures::future::try_join_all(
-        config.repositories.into_iter().map(|(name, repo_config)| {
-            tokio::task::spawn_blocking::<_, Result<_>>(move || {
-                let commits = find_unreleased_commits(&name, &repo_config)?;
-                Ok((name, commits.next_head, commits.unreleased_commits))
-            })
-        }),
-    )
-    .await?;
+    let mut shortcut_client = ShortcutClient::new(&api_key);
+    if let Ok(base_path) = var("SHORTCUT_API_URL") {
+        shortcut_client = shortcut_client.with_base_path(base_path);
+    }
+    Ok(shortcut_client)
+}
+
+/// Find the unreleased commits of the repositories, then retrieve their stories from Shortcut
+async fn fetch_release_content(
+    args: &Args,
+    config: &AppConfig,
+    shortcut_client: &ShortcutClient,
+) -> Result<(ReleaseContent, RepoToHeadCommit)> {
+    let repo_names_and_heads_and_commits =
+        futures::future::try_join_all(config.repositories.clone().into_iter().map(
+            |(name, repo_config)| {
+                tokio::task::spawn_blocking::<_, Result<_>>(move || {
+                    let (commits, origin_path) = find_unreleased_commits(&name, &repo_config)?;
+                    Ok((
+                        name,
+                        commits.next_head,
+                        commits.unreleased_commits,
+                        origin_path,
+                    ))
+                })
+            },
+        ))
+        .await?;
     let next_heads = repo_names_and_heads_and_commits
         .iter()
         .map(|repo_name_and_head_and_commit| {
-            let (repo_name, next_head, _commits) = repo_name_and_head_and_commit
+            let (repo_name, next_head, _commits, _origin_path) = repo_name_and_head_and_commit
                 .as_ref()
                 .map_err(|err| anyhow!("{:?}", err))?;
             Ok((repo_name.clone(), next_head.clone()))
         })
         .collect::<Result<HashMap<_, _>>>()?;
+    let origin_paths = repo_names_and_heads_and_commits
+        .iter()
+        .filter_map(|repo_name_and_head_and_commit| {
+            let (repo_name, _next_head, _commits, origin_path) =
+                repo_name_and_head_and_commit.as_ref().ok()?;
+            Some((repo_name.clone(), origin_path.clone()?))
+        })
+        .collect::<HashMap<_, _>>();
     let repo_names_and_commits = repo_names_and_heads_and_commits
         .into_iter()
-        .map_ok(|(repo_name, _next_head, commits)| (repo_name, commits))
+        .map_ok(|(repo_name, _next_head, commits, _origin_path)| (repo_name, commits))
         .collect::<Result<HashMap<_, _>>>()?;
     let exclude_story_ids = HashSet::from_iter(args.exclude_story_id.iter().copied());
-    let parsed_commits = parse_commits(repo_names_and_commits, &exclude_story_ids)?;
+    let parsed_commits =
+        parse_commits(repo_names_and_commits, &exclude_story_ids)?.with_origin_paths(origin_paths);
     debug!("Got result {:?}", parsed_commits);
-    let shortcut_client = ShortcutClient::new(&api_key);
     let release_content = shortcut_client
         .get_release(
             parsed_commits,
-            StoryLabelFilter::new(&args.exclude_story_label, &args.include_story_label),
+            StoryFilter::new(&args.exclude_story_label, &args.include_story_label)
+                .with_custom_fields(&args.exclude_custom_field, &args.include_custom_field)
+                .with_expression(args.filter.as_ref().or(config.filter.as_ref())),
+            ReleaseOptions {
+                include_iterations: args.include_iterations,
+                include_groups: args.include_groups,
+                include_objectives: args.include_objectives,
+                include_epic_stories: args.include_epic_stories,
+                vcs_lookup: args.vcs_lookup || args.vcs_lookup_query.is_some(),
+                vcs_lookup_query: args.vcs_lookup_query.clone(),
+                include_story_links: args.include_story_links,
+                fold_duplicates: args.fold_duplicates,
+                release_note_marker: args
+                    .release_notes
+                    .then(|| config.release_note_marker.clone()),
+            },
         )
         .await?;
-    print_summary(&release_content);
-    let include_unparsed_commits = !args.exclude_unparsed_commits;
-    let release = Release {
-        name: args.name.as_deref(),
-        version: args.version.as_deref(),
-        description: args.description.as_deref(),
-        stories: release_content.stories,
-        epics: release_content.epics,
-        unparsed_commits: include_unparsed_commits
-            .then_some(release_content.unparsed_commits)
-            .unwrap_or_default(),
-        next_heads,
+    Ok((release_content, next_heads))
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let _ = dotenvy::dotenv().ok();
+    // Logs and the summary go to the standard error, the release notes may go to the standard
+    // output
+    tracing_subscriber::fmt().with_writer(io::stderr).init();
+    let args = Args::parse();
+    match &args.command {
+        Some(Command::DumpTemplate { name, output_file }) => {
+            return dump_template(name, output_file.as_deref())
+        }
+        Some(Command::CheckTemplate { template }) => return check_template(template.as_deref()),
+        Some(Command::JsonSchema { output_file }) => {
+            return dump_json_schema(output_file.as_deref())
+        }
+        Some(Command::Preview { .. }) | None => {}
+    }
+    let config = AppConfig::parse(&PathBuf::from("config.toml"))?;
+    if let Some(Command::Preview {
+        template,
+        output_file,
+    }) = &args.command
+    {
+        return preview::preview(&args, &config, template.as_deref(), output_file.as_deref()).await;
+    }
+    let shortcut_client = shortcut_client()?;
+    let config_renderer = ConfigRenderer::new(args.version.as_deref(), args.name.as_deref());
+    let story_changes = if args.apply {
+        let apply_config = config
+            .apply
+            .as_ref()
+            .ok_or_else(|| anyhow!("--apply requires an [apply] section in config.toml"))?;
+        let story_changes = StoryChanges::render(apply_config, &config_renderer)?;
+        if story_changes.is_empty() {
+            bail!("The [apply] section of config.toml does not contain any change");
+        }
+        Some(story_changes)
+    } else {
+        None
+    };
+    let release_entity = if args.publish {
+        let release_entity_config = config.release_entity.as_ref().ok_or_else(|| {
+            anyhow!("--publish requires a [release_entity] section in config.toml")
+        })?;
+        Some(ReleaseEntity::render(
+            release_entity_config,
+            &config_renderer,
+        )?)
+    } else {
+        None
+    };
+    let mut output_configs = Vec::new();
+    if let (Some(output_file), OutputFormat::Template) = (&args.output_file, args.format) {
+        let template_file = args
+            .template
+            .clone()
+            .or_else(|| config.template_file.clone())
+            .ok_or_else(|| {
+                anyhow!("An output file requires --template or a template_file in config.toml")
+            })?;
+        output_configs.push(OutputConfig {
+            template: template_file,
+            path: output_file.clone(),
+            filter: None,
+            escape: None,
+            mode: args.mode,
+        });
+    }
+    output_configs.extend(config.outputs.iter().cloned());
+    if output_configs.is_empty() && args.output_file.is_none() {
+        bail!("Nothing to render, please provide an output file or [[outputs]] in config.toml");
+    }
+    if args.format != OutputFormat::Template && args.mode != WriteMode::Overwrite {
+        bail!("--mode requires --format template");
+    }
+    if output_configs
+        .iter()
+        .any(|output_config| output_config.mode == WriteMode::ReplaceSection)
+        && args.version.is_none()
+    {
+        bail!("The replace-section mode requires --version");
+    }
+    if args.dry_run && !args.apply && !args.publish {
+        bail!("--dry-run requires --apply or --publish");
+    }
+    if args.publish && output_configs.is_empty() {
+        bail!("--publish requires release notes rendered from a template");
+    }
+    let template_options = TemplateOptions::new(&config)?;
+    let templates = output_configs
+        .iter()
+        .map(|output_config| {
+            let template_options = TemplateOptions {
+                escape: output_config
+                    .escape
+                    .or_else(|| EscapeMode::from_path(&output_config.path)),
+                ..template_options.clone()
+            };
+            FileTemplate::load(&output_config.template, &template_options)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let (mut release_content, next_heads) =
+        fetch_release_content(&args, &config, &shortcut_client).await?;
+    print_summary(&release_content, args.release_notes);
+    let release = Release::new(&args, &mut release_content, next_heads);
+    let filter_context = FilterContext {
+        custom_fields: &release_content.custom_fields,
+        workflow_states: &release_content.workflow_states,
     };
-    template.render_to_file(&release, &args.output_file)?;
+    if let Some(output_file) = &args.output_file {
+        if let Some(exported_release) = export::export(&release, args.format)? {
+            output::write(output_file, &exported_release)?;
+            info!(path = %output_file.display(), "Release exported");
+        }
+    }
+    let mut rendered_outputs = Vec::with_capacity(output_configs.len());
+    for (output_config, template) in output_configs.iter().zip(&templates) {
+        let rendered_output = match &output_config.filter {
+            Some(filter) => template.render(&release.filtered(filter, &filter_context))?,
+            None => template.render(&release)?,
+        };
+        output::write_release_notes(
+            &output_config.path,
+            &rendered_output,
+            output_config.mode,
+            args.version.as_deref(),
+        )?;
+        info!(path = %output_config.path.display(), "Release notes written");
+        rendered_outputs.push(rendered_output);
+    }
+    // The release entity description is the first output: the output file if provided and
+    // rendered from the template, else the first of the [[outputs]]
+    let release_notes = rendered_outputs.first();
+    if let Some(story_changes) = story_changes {
+        let updates = shortcut_client
+            .plan_story_updates(&release.stories, &story_changes)
+            .await?;
+        apply::print_story_updates(&updates);
+        if !args.dry_run {
+            shortcut_client.apply_story_updates(updates).await?;
+        }
+    }
+    if let (Some(release_entity), Some(release_notes)) = (release_entity, release_notes) {
+        if args.dry_run {
+            eprintln!(
+                "\n{}: {} {:?}, linked to {} stories",
+                Style::new().bold().paint("Release to publish"),
+                release_entity.kind,
+                release_entity.name,
+                release.stories.len()
+            );
+        } else {
+            let url = shortcut_client
+                .publish_release_entity(&release_entity, release_notes, &release.stories)
+                .await?;
+            eprintln!(
+                "\n{}: {}",
+                Style::new().bold().paint("Release published"),
+                Blue.paint(url)
+            );
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,304 @@
+//! Writing of the rendered release notes to their destination file, either overwriting it or
+//! inserting the release into an existing file such as a cumulative `CHANGELOG.md`.
+use std::{
+    fs,
+    io::{self, ErrorKind, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+/// Path standing for the standard output
+pub const STDOUT_PATH: &str = "-";
+
+/// Comment below which the releases are inserted in the `prepend` and `replace-section` modes
+pub const INSERTION_MARKER: &str = "<!-- releases -->";
+
+/// How the rendered release notes are written to the destination file
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum WriteMode {
+    /// Replace the content of the file
+    #[default]
+    Overwrite,
+    /// Insert the release below the insertion marker, or at the start of the file
+    Prepend,
+    /// Insert the release at the end of the file
+    Append,
+    /// Replace the section of the same version if the file contains one, else prepend the release
+    ReplaceSection,
+}
+
+/// Comments delimiting the section of a version
+fn section_markers(version: &str) -> (String, String) {
+    (
+        format!("<!-- release:{} -->", version),
+        format!("<!-- end release:{} -->", version),
+    )
+}
+
+/// Release notes wrapped in the comments delimiting the section of the version, if any
+fn section(release_notes: &str, version: Option<&str>) -> String {
+    let release_notes = release_notes.trim_end();
+    match version {
+        Some(version) => {
+            let (start, end) = section_markers(version);
+            format!("{}\n{}\n{}", start, release_notes, end)
+        }
+        None => release_notes.to_owned(),
+    }
+}
+
+fn prepend(content: &str, section: &str) -> String {
+    match content.find(INSERTION_MARKER) {
+        Some(index) => {
+            let (before, after) = content.split_at(index + INSERTION_MARKER.len());
+            let after = after.trim_start_matches(['\r', '\n']);
+            let separator = if after.is_empty() { "" } else { "\n" };
+            format!("{}\n\n{}\n{}{}", before, section, separator, after)
+        }
+        None if content.is_empty() => format!("{}\n\n{}\n", INSERTION_MARKER, section),
+        None => format!("{}\n\n{}", section, content),
+    }
+}
+
+fn append(content: &str, section: &str) -> String {
+    let content = content.trim_end_matches(['\r', '\n']);
+    if content.is_empty() {
+        format!("{}\n", section)
+    } else {
+        format!("{}\n\n{}\n", content, section)
+    }
+}
+
+/// Replace the section of the version, `None` if the content does not contain it. A start
+/// marker without its end marker is an error, rather than guessing where the section ends.
+fn replace_section(content: &str, section: &str, version: &str) -> Result<Option<String>> {
+    let (start_marker, end_marker) = section_markers(version);
+    let Some(start) = content.find(&start_marker) else {
+        return Ok(None);
+    };
+    let end = content[start..]
+        .find(&end_marker)
+        .map(|index| start + index + end_marker.len())
+        .ok_or_else(|| anyhow!("Found {} without a matching {}", start_marker, end_marker))?;
+    Ok(Some(format!(
+        "{}{}{}",
+        &content[..start],
+        section,
+        &content[end..]
+    )))
+}
+
+/// Insert the release notes into the content of the destination file, according to the mode
+fn insert(
+    content: &str,
+    release_notes: &str,
+    mode: WriteMode,
+    version: Option<&str>,
+) -> Result<String> {
+    let section = section(release_notes, version);
+    Ok(match mode {
+        WriteMode::Overwrite => release_notes.to_owned(),
+        WriteMode::Prepend => prepend(content, &section),
+        WriteMode::Append => append(content, &section),
+        WriteMode::ReplaceSection => {
+            let version =
+                version.ok_or_else(|| anyhow!("The replace-section mode requires --version"))?;
+            replace_section(content, &section, version)?
+                .unwrap_or_else(|| prepend(content, &section))
+        }
+    })
+}
+
+/// Whether the path stands for the standard output
+pub fn is_stdout(path: &Path) -> bool {
+    path == Path::new(STDOUT_PATH)
+}
+
+/// Write the content to the destination file, or to the standard output if the path is `-`
+pub fn write(path: &Path, content: &str) -> Result<()> {
+    if is_stdout(path) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(content.as_bytes())?;
+        stdout.flush()?;
+        return Ok(());
+    }
+    fs::write(path, content).map_err(|err| anyhow!("Could not write {}: {}", path.display(), err))
+}
+
+/// Write the release notes to the destination file, according to the mode. The release notes of
+/// the `prepend`, `append` and `replace-section` modes are wrapped in comments delimiting the
+/// section of the version, if any, so that later runs can replace them.
+pub fn write_release_notes(
+    path: &Path,
+    release_notes: &str,
+    mode: WriteMode,
+    version: Option<&str>,
+) -> Result<()> {
+    if is_stdout(path) && mode != WriteMode::Overwrite {
+        bail!("Release notes written to the standard output can only use the overwrite mode");
+    }
+    let content = match mode {
+        WriteMode::Overwrite => String::new(),
+        _ => match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => bail!("Could not read {}: {}", path.display(), err),
+        },
+    };
+    write(path, &insert(&content, release_notes, mode, version)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, path::PathBuf, process};
+
+    use super::*;
+
+    const CHANGELOG: &str = "# Changelog\n\n<!-- releases -->\n\n## 1.0.0\n\n- First release\n";
+
+    /// Path of a file which does not exist, in a directory removed when dropped
+    struct MissingFile(PathBuf);
+
+    impl MissingFile {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!(
+                "shortcut_release_helper-{}-{}",
+                process::id(),
+                name
+            ));
+            fs::create_dir_all(&dir).expect("Should create the temporary directory");
+            Self(dir.join("CHANGELOG.md"))
+        }
+    }
+
+    impl Drop for MissingFile {
+        fn drop(&mut self) {
+            if let Some(dir) = self.0.parent() {
+                let _ = fs::remove_dir_all(dir);
+            }
+        }
+    }
+
+    #[test]
+    fn prepend_creates_a_missing_file_with_the_marker() {
+        let file = MissingFile::new("prepend");
+        write_release_notes(&file.0, "## 1.1.0\n", WriteMode::Prepend, Some("1.1.0")).unwrap();
+        assert_eq!(
+            fs::read_to_string(&file.0).unwrap(),
+            "<!-- releases -->\n\n<!-- release:1.1.0 -->\n## 1.1.0\n<!-- end release:1.1.0 -->\n"
+        );
+    }
+
+    #[test]
+    fn append_creates_a_missing_file() {
+        let file = MissingFile::new("append");
+        write_release_notes(&file.0, "## 1.1.0\n", WriteMode::Append, None).unwrap();
+        assert_eq!(fs::read_to_string(&file.0).unwrap(), "## 1.1.0\n");
+    }
+
+    #[test]
+    fn prepend_inserts_below_the_marker() {
+        assert_eq!(
+            insert(CHANGELOG, "## 1.1.0\n", WriteMode::Prepend, None).unwrap(),
+            "# Changelog\n\n<!-- releases -->\n\n## 1.1.0\n\n## 1.0.0\n\n- First release\n"
+        );
+    }
+
+    #[test]
+    fn prepend_inserts_at_the_start_without_marker() {
+        assert_eq!(
+            insert("## 1.0.0\n", "## 1.1.0\n", WriteMode::Prepend, None).unwrap(),
+            "## 1.1.0\n\n## 1.0.0\n"
+        );
+    }
+
+    #[test]
+    fn append_inserts_at_the_end() {
+        assert_eq!(
+            insert(CHANGELOG, "## 1.1.0\n", WriteMode::Append, None).unwrap(),
+            format!("{}\n## 1.1.0\n", CHANGELOG)
+        );
+        assert_eq!(
+            insert("## 1.0.0\n", "## 1.1.0\n", WriteMode::Append, None).unwrap(),
+            "## 1.0.0\n\n## 1.1.0\n"
+        );
+    }
+
+    #[test]
+    fn overwrite_ignores_the_content() {
+        assert_eq!(
+            insert(CHANGELOG, "## 1.1.0\n", WriteMode::Overwrite, Some("1.1.0")).unwrap(),
+            "## 1.1.0\n"
+        );
+    }
+
+    #[test]
+    fn replace_section_prepends_a_new_version() {
+        assert_eq!(
+            insert(
+                CHANGELOG,
+                "## 1.1.0\n",
+                WriteMode::ReplaceSection,
+                Some("1.1.0")
+            )
+            .unwrap(),
+            "# Changelog\n\n<!-- releases -->\n\n<!-- release:1.1.0 -->\n## 1.1.0\n\
+             <!-- end release:1.1.0 -->\n\n## 1.0.0\n\n- First release\n"
+        );
+    }
+
+    #[test]
+    fn replace_section_is_idempotent() {
+        let first = insert(
+            CHANGELOG,
+            "## 1.1.0\n",
+            WriteMode::ReplaceSection,
+            Some("1.1.0"),
+        )
+        .unwrap();
+        let second = insert(
+            &first,
+            "## 1.1.0\n",
+            WriteMode::ReplaceSection,
+            Some("1.1.0"),
+        )
+        .unwrap();
+        assert_eq!(first.as_bytes(), second.as_bytes());
+        let updated = insert(
+            &first,
+            "## 1.1.0\n\n- Fixed\n",
+            WriteMode::ReplaceSection,
+            Some("1.1.0"),
+        )
+        .unwrap();
+        assert_eq!(
+            updated,
+            first.replace("## 1.1.0\n", "## 1.1.0\n\n- Fixed\n")
+        );
+    }
+
+    #[test]
+    fn replace_section_requires_the_end_marker() {
+        let content = "<!-- releases -->\n\n<!-- release:1.1.0 -->\n## 1.1.0\n";
+        let err = insert(
+            content,
+            "## 1.1.0\n",
+            WriteMode::ReplaceSection,
+            Some("1.1.0"),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Found <!-- release:1.1.0 --> without a matching <!-- end release:1.1.0 -->"
+        );
+    }
+
+    #[test]
+    fn replace_section_requires_a_version() {
+        assert!(insert(CHANGELOG, "## 1.1.0\n", WriteMode::ReplaceSection, None).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! Live preview of a template: the release is retrieved once and kept in memory, then the
+//! template is rendered again whenever it (or a template it includes) changes.
+use std::{
+    fs,
+    io::{self, IsTerminal},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use ansi_term::{
+    Colour::{Blue, Green, Red},
+    Style,
+};
+use anyhow::{anyhow, bail, Result};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use tokio::{sync::mpsc, time};
+
+use crate::{
+    config::AppConfig,
+    fetch_release_content, output, print_summary, shortcut_client,
+    template::{EscapeMode, FileTemplate, TemplateOptions},
+    Args, Release,
+};
+
+/// Delay without any change before rendering the template again, as editors often save a file
+/// in several steps
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(100);
+
+/// Render the template to the output file or the terminal, then again on every change of the
+/// template directory, until interrupted
+pub(crate) async fn preview(
+    args: &Args,
+    config: &AppConfig,
+    template: Option<&Path>,
+    output_file: Option<&Path>,
+) -> Result<()> {
+    let template = template
+        .map(Path::to_path_buf)
+        .or_else(|| args.template.clone())
+        .or_else(|| config.template_file.clone())
+        .ok_or_else(|| anyhow!("Please provide a template, or a template_file in config.toml"))?;
+    let template_dir = match &config.template_dir {
+        Some(template_dir) => template_dir.clone(),
+        None => template
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf(),
+    };
+    // Neither built-in templates nor the standard input can be watched
+    if !template.is_file() {
+        bail!("{} is not a template file", template.display());
+    }
+    let output_file = output_file.unwrap_or_else(|| Path::new(output::STDOUT_PATH));
+    let template_options = TemplateOptions {
+        escape: EscapeMode::from_path(output_file),
+        ..TemplateOptions::new(config)?
+    };
+    // Report template errors before the release is retrieved
+    FileTemplate::load(&template, &template_options)?;
+
+    let shortcut_client = shortcut_client()?;
+    let (mut release_content, next_heads) =
+        fetch_release_content(args, config, &shortcut_client).await?;
+    print_summary(&release_content, args.release_notes);
+    let release = Release::new(args, &mut release_content, next_heads);
+
+    render(&template, &template_options, &release, output_file);
+    // Writing the output file must not trigger another rendering, if it is in the template
+    // directory
+    let output_path = fs::canonicalize(output_file).ok();
+    // The watcher sends the events from its own thread, without blocking
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        // Sending only fails once the preview has stopped
+        let _ = sender.send(event);
+    })?;
+    watcher.watch(&fs::canonicalize(&template_dir)?, RecursiveMode::Recursive)?;
+    eprintln!(
+        "{} {} {}",
+        Style::new().bold().paint("Watching"),
+        Blue.paint(template_dir.display().to_string()),
+        Style::new().dimmed().paint("(press Ctrl+C to stop)")
+    );
+    while let Some(event) = receiver.recv().await {
+        let event = event?;
+        let is_template_change = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+            && event
+                .paths
+                .iter()
+                .any(|path| Some(path) != output_path.as_ref());
+        if !is_template_change {
+            continue;
+        }
+        while let Ok(Some(_)) = time::timeout(DEBOUNCE_DELAY, receiver.recv()).await {}
+        render(&template, &template_options, &release, output_file);
+    }
+    Ok(())
+}
+
+/// Load and render the template, reporting errors without stopping the preview
+fn render(template: &Path, options: &TemplateOptions, release: &Release, output_file: &Path) {
+    let now = Instant::now();
+    let to_terminal = output::is_stdout(output_file) && io::stdout().is_terminal();
+    let result = FileTemplate::load(template, options)
+        .and_then(|template| template.render(release))
+        .and_then(|rendered| {
+            if to_terminal {
+                // Clear the terminal, to only display the last rendering
+                print!("\x1b[2J\x1b[H");
+            }
+            output::write(output_file, &rendered)
+        });
+    match result {
+        Ok(()) => eprintln!(
+            "\n{} in {}ms",
+            Green.paint("Rendered"),
+            now.elapsed().as_millis()
+        ),
+        Err(err) => eprintln!("\n{} {:#}", Red.paint("error:"), err),
+    }
+}
@@ -1,4 +1,5 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::iter;
 use std::num::NonZeroU32;
 use std::{collections::HashMap, str::FromStr};
 
@@ -11,41 +12,217 @@ use governor::state::InMemoryState;
 use governor::state::NotKeyed;
 use governor::Quota;
 use governor::RateLimiter;
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use regex::Regex;
-use serde::Serialize;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{de::DeserializeOwned, ser, Serialize, Serializer};
+use serde_json::json;
 use shortcut_client::apis::configuration as shortcut_cfg;
 use shortcut_client::apis::default_api as shortcut_api;
-use shortcut_client::models::{Epic, Story};
+use shortcut_client::models::{
+    CustomField, Epic, Group, Iteration, Member, Objective, Story, StorySlim, UpdateStory, Workflow,
+};
+use tracing::{debug, warn};
 
-use crate::types::RepoToCommits;
-use crate::types::ShortcutApiKey;
+use crate::apply::StoryChanges;
+use crate::config::ReleaseEntityKind;
+use crate::export::ShortcutEntity;
+use crate::filter::{FilterContext, FilterExpression};
+use crate::types::{CustomFieldValue, ShortcutApiKey};
+use crate::types::{RepoToCommits, RepositoryName, UnreleasedCommit};
 
 #[derive(Debug)]
 pub struct Commits {
     story_commits: HashMap<StoryId, RepoToCommits>,
     unparsed_commits: RepoToCommits,
+    excluded_story_ids: HashSet<StoryId>,
+    /// Host and path of the `origin` remote of the repositories, e.g. `github.com/acme/app`
+    origin_paths: HashMap<RepositoryName, String>,
 }
 
+impl Commits {
+    /// Commits whose repositories are identified by the host and path of their `origin`
+    /// remote, to only link them to the pull requests and branches of the same repository
+    pub fn with_origin_paths(self, origin_paths: HashMap<RepositoryName, String>) -> Self {
+        Self {
+            origin_paths,
+            ..self
+        }
+    }
+
+    /// Link unparsed commits to the candidate stories, based on the commits, branches and pull
+    /// requests reported by Shortcut's VCS integration for each story. Returns the ids of the
+    /// stories linked to at least one commit.
+    fn link_vcs_commits<'a>(
+        &mut self,
+        candidates: impl Iterator<Item = &'a Story>,
+    ) -> HashSet<i64> {
+        let candidates = candidates
+            .filter(|story| !self.excluded_story_ids.contains(&StoryId(story.id as u32)))
+            .collect::<Vec<_>>();
+        let mut linked_story_ids = HashSet::new();
+        for (repo_name, commits) in self.unparsed_commits.iter_mut() {
+            let origin_path = self.origin_paths.get(repo_name).map(String::as_str);
+            let mut remaining_commits = Vec::new();
+            for commit in commits.drain(..) {
+                match candidates
+                    .iter()
+                    .find(|story| is_vcs_commit_of_story(story, &commit, origin_path))
+                {
+                    Some(story) => {
+                        debug!("Linked commit {} to story {}", commit.id, story.id);
+                        linked_story_ids.insert(story.id);
+                        self.story_commits
+                            .entry(StoryId(story.id as u32))
+                            .or_default()
+                            .entry(repo_name.clone())
+                            .or_default()
+                            .push(commit);
+                    }
+                    None => remaining_commits.push(commit),
+                }
+            }
+            *commits = remaining_commits;
+        }
+        linked_story_ids
+    }
+}
+
+/// Whether the commit is known by Shortcut to belong to the story, either directly or via a
+/// pull request number (e.g. `Fix login (#123)`) or branch name (e.g. `Merge branch 'fix-login'`)
+/// found in the first line of the commit message. Only the pull requests and branches of the
+/// commit's repository, given by the host and path of its `origin` remote, are considered.
+fn is_vcs_commit_of_story(
+    story: &Story,
+    commit: &UnreleasedCommit,
+    origin_path: Option<&str>,
+) -> bool {
+    lazy_static! {
+        static ref PULL_REQUEST_RE: Regex =
+            Regex::new(r"^Merge pull request #(\d+)|\(#(\d+)\)\s*$")
+                .expect("Could not compile PULL_REQUEST_RE");
+    };
+    let commit_id = commit.id.to_string();
+    if story
+        .commits
+        .iter()
+        .any(|story_commit| story_commit.hash == commit_id)
+    {
+        return true;
+    }
+    let (Some(origin_path), Some(first_line)) = (
+        origin_path,
+        commit
+            .message
+            .as_deref()
+            .and_then(|message| message.lines().next()),
+    ) else {
+        return false;
+    };
+    // e.g. `https://github.com/acme/app/pull/123` for the `github.com/acme/app` repository
+    let is_in_repository = |url: &str| {
+        url.split_once("://")
+            .map_or(url, |(_scheme, location)| location)
+            .to_lowercase()
+            .starts_with(&format!("{}/", origin_path))
+    };
+    let pull_requests = story
+        .pull_requests
+        .iter()
+        .filter(|pull_request| is_in_repository(&pull_request.url))
+        .collect::<Vec<_>>();
+    let pull_request_number = PULL_REQUEST_RE
+        .captures(first_line)
+        .and_then(|captures| captures.get(1).or_else(|| captures.get(2)))
+        .and_then(|number| number.as_str().parse::<i64>().ok());
+    if let Some(pull_request_number) = pull_request_number {
+        if pull_requests
+            .iter()
+            .any(|pull_request| pull_request.number == pull_request_number)
+        {
+            return true;
+        }
+    }
+    story
+        .branches
+        .iter()
+        .filter(|branch| is_in_repository(&branch.url))
+        .map(|branch| branch.name.as_str())
+        .chain(
+            pull_requests
+                .iter()
+                .map(|pull_request| pull_request.branch_name.as_str()),
+        )
+        .any(|branch_name| {
+            first_line.contains(&format!("'{}'", branch_name))
+                || first_line.ends_with(&format!("/{}", branch_name))
+        })
+}
+
+/// Filter on the labels and custom field values of stories, optionally combined with a filter
+/// expression
 #[derive(Debug)]
-pub struct StoryLabelFilter<'a> {
+pub struct StoryFilter<'a> {
     excluded_labels: HashSet<&'a String>,
     included_labels: HashSet<&'a String>,
+    excluded_custom_fields: &'a [CustomFieldValue],
+    included_custom_fields: &'a [CustomFieldValue],
+    expression: Option<&'a FilterExpression>,
 }
 
-impl<'a> StoryLabelFilter<'a> {
+impl<'a> StoryFilter<'a> {
     pub fn new(excluded_labels: &'a [String], included_labels: &'a [String]) -> Self {
         Self {
             excluded_labels: HashSet::from_iter(excluded_labels.iter()),
             included_labels: HashSet::from_iter(included_labels.iter()),
+            excluded_custom_fields: &[],
+            included_custom_fields: &[],
+            expression: None,
+        }
+    }
+
+    pub fn with_custom_fields(
+        self,
+        excluded_custom_fields: &'a [CustomFieldValue],
+        included_custom_fields: &'a [CustomFieldValue],
+    ) -> Self {
+        Self {
+            excluded_custom_fields,
+            included_custom_fields,
+            ..self
         }
     }
 
+    pub fn with_expression(self, expression: Option<&'a FilterExpression>) -> Self {
+        Self { expression, ..self }
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.excluded_labels.is_empty() && self.included_labels.is_empty()
+        self.excluded_labels.is_empty()
+            && self.included_labels.is_empty()
+            && self.excluded_custom_fields.is_empty()
+            && self.included_custom_fields.is_empty()
+            && self.expression.is_none()
     }
 
-    pub fn filter(&self, story: &Story) -> bool {
+    /// Whether the story should be kept. Exclusions have priority over inclusions, and a story
+    /// must match all inclusions, as well as the filter expression, to be kept.
+    pub fn filter(&self, story: &Story, context: &FilterContext) -> bool {
+        if let Some(expression) = self.expression {
+            if !expression.matches(story, context) {
+                return false;
+            }
+        }
+        if !self.excluded_custom_fields.is_empty() || !self.included_custom_fields.is_empty() {
+            let story_values = context.custom_fields.story_values(story);
+            let has_value = |custom_field: &CustomFieldValue| custom_field.is_in(&story_values);
+            if self.excluded_custom_fields.iter().any(has_value)
+                || !self.included_custom_fields.iter().all(has_value)
+            {
+                return false;
+            }
+        }
         let mut included_labels_count = 0;
         for label in &story.labels {
             if self.excluded_labels.contains(&label.name) {
@@ -60,6 +237,73 @@ impl<'a> StoryLabelFilter<'a> {
     }
 }
 
+/// Custom field definitions of the organization
+#[derive(Debug, Default)]
+pub struct CustomFields(Vec<CustomField>);
+
+impl CustomFields {
+    /// Values of the custom fields of the story, keyed by field name
+    pub fn story_values(&self, story: &Story) -> BTreeMap<String, String> {
+        story
+            .custom_fields
+            .iter()
+            .filter_map(|story_field| {
+                self.0
+                    .iter()
+                    .find(|field| field.id == story_field.field_id)
+                    .map(|field| (field.name.clone(), story_field.value.clone()))
+            })
+            .collect()
+    }
+
+    /// Resolve a custom field value by names, returning the field id and the value id
+    fn find_value_ids(&self, custom_field: &CustomFieldValue) -> Result<(String, String)> {
+        let field = self
+            .0
+            .iter()
+            .find(|field| field.name == custom_field.name)
+            .ok_or_else(|| anyhow!("Unknown custom field {:?}", custom_field.name))?;
+        let value_id = field
+            .values
+            .iter()
+            .flatten()
+            .find(|value| value.value == custom_field.value)
+            .map(|value| value.id.clone())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown value {:?} for custom field {:?}",
+                    custom_field.value,
+                    custom_field.name
+                )
+            })?;
+        Ok((field.id.clone(), value_id))
+    }
+}
+
+/// Optional data to retrieve along with the stories and epics of the release
+#[derive(Debug, Default, Clone)]
+pub struct ReleaseOptions {
+    /// Retrieve the iterations of the stories
+    pub include_iterations: bool,
+    /// Retrieve the groups (teams) of the stories
+    pub include_groups: bool,
+    /// Retrieve the objectives (formerly milestones) of the epics
+    pub include_objectives: bool,
+    /// Retrieve the stories of the epics, to find the ones missing from the release
+    pub include_epic_stories: bool,
+    /// Link unparsed commits to stories via Shortcut's VCS integration
+    pub vcs_lookup: bool,
+    /// Resolve the stories linked to the stories of the release
+    pub include_story_links: bool,
+    /// Replace the stories duplicating another story by the latter
+    pub fold_duplicates: bool,
+    /// Search query for additional stories to link unparsed commits to, on top of the stories
+    /// of the release
+    pub vcs_lookup_query: Option<String>,
+    /// Extract the release note of each story from its comments, starting with this marker
+    pub release_note_marker: Option<String>,
+}
+
 /// not linked to a story.
 pub fn parse_commits(
     commits: RepoToCommits,
@@ -105,9 +349,16 @@ pub fn parse_commits(
     Ok(Commits {
         story_commits,
         unparsed_commits,
+        excluded_story_ids: exclude_story_ids.clone(),
+        origin_paths: HashMap::new(),
     })
 }
 
+/// Maximum number of story ids sent in a single search query
+const SEARCH_STORY_IDS_PER_QUERY: usize = 25;
+/// Number of results per search page, the maximum accepted by the Shortcut API
+const SEARCH_PAGE_SIZE: i64 = 25;
+
 pub struct ShortcutClient {
     configuration: shortcut_cfg::Configuration,
     rate_limiter: RateLimiter<NotKeyed, InMemoryState, QuantaClock>,
@@ -129,6 +380,13 @@ impl ShortcutClient {
         }
     }
 
+    /// Send the API requests to another server than the official Shortcut API, such as a local
+    /// stand-in server.
+    pub fn with_base_path(mut self, base_path: String) -> Self {
+        self.configuration.base_path = base_path;
+        self
+    }
+
     async fn get_shortcut_data<T: std::fmt::Debug + Unpin, E: std::fmt::Debug + Unpin>(
         &self,
         actions: impl Iterator<Item = impl Future<Output = Result<T, E>>>,
@@ -150,40 +408,291 @@ impl ShortcutClient {
         Ok(items)
     }
 
+    /// Like [`Self::get_shortcut_data`], but skipping the failed actions with a warning
+    async fn get_shortcut_data_leniently<T: Unpin, E: std::fmt::Debug + Unpin>(
+        &self,
+        actions: impl Iterator<Item = impl Future<Output = Result<T, E>>>,
+    ) -> Vec<T> {
+        actions
+            .collect::<FuturesUnordered<_>>()
+            .ratelimit_stream(&self.rate_limiter)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .filter_map(|item| item.map_err(|err| warn!("{:?}", err)).ok())
+            .collect()
+    }
+
     pub async fn get_release<'a>(
         &self,
-        commits: Commits,
-        story_label_filter: StoryLabelFilter<'a>,
+        mut commits: Commits,
+        story_filter: StoryFilter<'a>,
+        options: ReleaseOptions,
     ) -> Result<ReleaseContent> {
-        let mut stories = self.get_stories(&commits).await?;
-        if !story_label_filter.is_empty() {
-            stories.retain(|story| story_label_filter.filter(story));
+        let custom_fields = self.get_custom_fields().await?;
+        let workflow_states = self.get_workflow_states().await?;
+        let mut stories = self
+            .get_stories(commits.story_commits.keys().copied().collect())
+            .await?;
+        if options.vcs_lookup {
+            let mut candidates = match &options.vcs_lookup_query {
+                Some(query) => self.search_stories(query).await?,
+                None => Vec::new(),
+            };
+            candidates.retain(|candidate| !stories.iter().any(|story| story.id == candidate.id));
+            let linked_story_ids =
+                commits.link_vcs_commits(stories.iter().chain(candidates.iter()));
+            debug!(
+                "Linked unparsed commits to {} stories",
+                linked_story_ids.len()
+            );
+            stories.extend(
+                candidates
+                    .into_iter()
+                    .filter(|candidate| linked_story_ids.contains(&candidate.id)),
+            );
+            stories.sort_by_key(|story| story.id);
         }
+        if !story_filter.is_empty() {
+            let filter_context = FilterContext {
+                custom_fields: &custom_fields,
+                workflow_states: &workflow_states,
+            };
+            stories.retain(|story| story_filter.filter(story, &filter_context));
+        }
+        let linked_stories = if options.include_story_links || options.fold_duplicates {
+            self.get_linked_stories(&stories).await?
+        } else {
+            HashMap::new()
+        };
+        let mut folded_duplicates = if options.fold_duplicates {
+            fold_duplicates(&mut stories, &linked_stories)
+        } else {
+            HashMap::new()
+        };
+        let mut story_links = if options.include_story_links {
+            stories
+                .iter()
+                .map(|story| {
+                    (
+                        story.id,
+                        resolve_story_links(story, &stories, &linked_stories),
+                    )
+                })
+                .collect()
+        } else {
+            HashMap::new()
+        };
         let epics = self.get_epics(stories.iter()).await?;
+        let iterations = if options.include_iterations {
+            self.get_iterations(stories.iter()).await?
+        } else {
+            Vec::new()
+        };
+        let groups = if options.include_groups {
+            self.get_groups(stories.iter()).await?
+        } else {
+            Vec::new()
+        };
+        let objectives = if options.include_objectives {
+            self.get_objectives(epics.iter()).await?
+        } else {
+            Vec::new()
+        };
+        let mut epic_stories_not_in_release = if options.include_epic_stories {
+            self.get_epic_stories_not_in_release(&epics, &stories)
+                .await?
+        } else {
+            HashMap::new()
+        };
+        let epics = epics
+            .into_iter()
+            .map(|epic| ReleaseEpic {
+                stories_not_in_release: epic_stories_not_in_release.remove(&epic.id),
+                epic,
+            })
+            .collect();
+        let members = self.get_members().await?;
         let Commits {
-            unparsed_commits, ..
+            mut story_commits,
+            unparsed_commits,
+            ..
         } = commits;
+        // The commits of the folded stories belong to their canonical story
+        for (canonical_id, duplicates) in &folded_duplicates {
+            for duplicate in duplicates {
+                if let Some(duplicate_commits) = story_commits.remove(&StoryId(duplicate.id as u32))
+                {
+                    let canonical_commits = story_commits
+                        .entry(StoryId(*canonical_id as u32))
+                        .or_default();
+                    for (repo_name, commits) in duplicate_commits {
+                        canonical_commits
+                            .entry(repo_name)
+                            .or_default()
+                            .extend(commits);
+                    }
+                }
+            }
+        }
+        let stories = stories
+            .into_iter()
+            .map(|story| ReleaseStory {
+                release_commits: story_commits
+                    .remove(&StoryId(story.id as u32))
+                    .unwrap_or_default(),
+                workflow_state: workflow_states.get(&story.workflow_state_id).cloned(),
+                custom_fields: custom_fields.story_values(&story),
+                linked_stories: story_links.remove(&story.id).unwrap_or_default(),
+                folded_duplicates: folded_duplicates.remove(&story.id).unwrap_or_default(),
+                release_note: options
+                    .release_note_marker
+                    .as_deref()
+                    .and_then(|marker| story_release_note(&story, marker)),
+                story,
+            })
+            .collect();
         let release = ReleaseContent {
             stories,
             epics,
+            iterations,
+            groups,
+            objectives,
+            members,
             unparsed_commits,
+            custom_fields,
+            workflow_states,
         };
         Ok(release)
     }
 
-    async fn get_stories(&self, commits: &Commits) -> Result<Vec<Story>> {
-        let mut stories: Vec<Story> = self
-            .get_shortcut_data(commits.story_commits.keys().map(|story_id| {
-                let story_id: &u32 = story_id.as_ref();
-                shortcut_api::get_story(&self.configuration, *story_id as i64).map_err(move |err| {
-                    anyhow!("Error while retrieving story {}: {:?}", story_id, err)
-                })
-            }))
+    /// Retrieve the stories via the search endpoint, by pages, then fetch one by one the stories
+    /// the search did not return.
+    async fn get_stories(&self, story_ids: Vec<StoryId>) -> Result<Vec<Story>> {
+        let (mut stories, missing_story_ids) = self.search_stories_by_id(story_ids).await;
+        let missing_stories = self
+            .get_shortcut_data(self.get_each_story(missing_story_ids))
             .await?;
+        stories.extend(missing_stories);
         stories.sort_by_key(|story| story.id);
         Ok(stories)
     }
 
+    /// Like [`Self::get_stories`], but skipping the stories which cannot be retrieved (e.g.
+    /// deleted stories) with a warning
+    async fn get_stories_leniently(&self, story_ids: Vec<StoryId>) -> Vec<Story> {
+        let (mut stories, missing_story_ids) = self.search_stories_by_id(story_ids).await;
+        let missing_stories = self
+            .get_shortcut_data_leniently(self.get_each_story(missing_story_ids))
+            .await;
+        stories.extend(missing_stories);
+        stories.sort_by_key(|story| story.id);
+        stories
+    }
+
+    /// Requests retrieving the stories one by one
+    fn get_each_story(
+        &self,
+        story_ids: Vec<StoryId>,
+    ) -> impl Iterator<Item = impl Future<Output = Result<Story>> + '_> + '_ {
+        story_ids.into_iter().map(|story_id| {
+            let story_id = u32::from(story_id);
+            shortcut_api::get_story(&self.configuration, story_id as i64)
+                .map_err(move |err| anyhow!("Error while retrieving story {}: {:?}", story_id, err))
+        })
+    }
+
+    /// Retrieve the stories linked to the given stories, excluding the given stories themselves.
+    /// Linked stories which cannot be retrieved are skipped.
+    async fn get_linked_stories(&self, stories: &[Story]) -> Result<HashMap<i64, Story>> {
+        let story_ids = stories.iter().map(|story| story.id).collect::<HashSet<_>>();
+        let linked_story_ids = stories
+            .iter()
+            .flat_map(|story| story.story_links.iter())
+            .flat_map(|link| [link.subject_id, link.object_id])
+            .filter(|story_id| !story_ids.contains(story_id))
+            .unique()
+            .map(|story_id| {
+                u32::try_from(story_id)
+                    .map(StoryId)
+                    .map_err(|_| anyhow!("Invalid linked story id {}", story_id))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        debug!("Retrieving {} linked stories", linked_story_ids.len());
+        let linked_stories = self.get_stories_leniently(linked_story_ids).await;
+        Ok(linked_stories
+            .into_iter()
+            .map(|story| (story.id, story))
+            .collect())
+    }
+
+    /// Search stories by id, returning the requested stories found along with the ids of the
+    /// missing ones. Search errors are not fatal: the caller is expected to fetch any missing
+    /// story.
+    async fn search_stories_by_id(&self, story_ids: Vec<StoryId>) -> (Vec<Story>, Vec<StoryId>) {
+        let requested_ids = story_ids
+            .iter()
+            .map(|story_id| i64::from(u32::from(*story_id)))
+            .collect::<HashSet<_>>();
+        let mut stories = Vec::new();
+        for story_ids in story_ids.chunks(SEARCH_STORY_IDS_PER_QUERY) {
+            let query = format!("id:{}", story_ids.iter().join(","));
+            match self.search_stories(&query).await {
+                Ok(found_stories) => stories.extend(found_stories.into_iter().filter(|story| {
+                    let is_requested = requested_ids.contains(&story.id);
+                    if !is_requested {
+                        debug!("Ignoring unrequested story {}", story.id);
+                    }
+                    is_requested
+                })),
+                Err(err) => warn!("{:?}", err),
+            }
+        }
+        let found_story_ids = stories.iter().map(|story| story.id).collect::<HashSet<_>>();
+        let missing_story_ids = story_ids
+            .into_iter()
+            .filter(|story_id| !found_story_ids.contains(&i64::from(u32::from(*story_id))))
+            .collect::<Vec<_>>();
+        debug!(
+            "Found {found} stories via search, fetching {missing} stories one by one",
+            found = stories.len(),
+            missing = missing_story_ids.len()
+        );
+        (stories, missing_story_ids)
+    }
+
+    /// Search stories with a Shortcut search query, following the result pages
+    async fn search_stories(&self, query: &str) -> Result<Vec<Story>> {
+        let mut stories = Vec::new();
+        let mut next_page: Option<String> = None;
+        loop {
+            self.rate_limiter.until_ready().await;
+            let results = shortcut_api::search_stories(
+                &self.configuration,
+                query,
+                Some(SEARCH_PAGE_SIZE),
+                Some("full"),
+                next_page.as_deref(),
+                None,
+            )
+            .await
+            .map_err(|err| anyhow!("Error while searching stories with {:?}: {:?}", query, err))?;
+            for result in results.data {
+                // Search results share the story schema, but are a distinct type in the
+                // generated client
+                match serde_json::to_value(result).and_then(serde_json::from_value::<Story>) {
+                    Ok(story) => stories.push(story),
+                    Err(err) => warn!("Could not convert search result: {:?}", err),
+                }
+            }
+            next_page = results.next.as_deref().and_then(next_page_token);
+            if next_page.is_none() {
+                break;
+            }
+        }
+        Ok(stories)
+    }
+
     async fn get_epics(&self, stories: impl Iterator<Item = &Story>) -> Result<Vec<Epic>> {
         let epic_ids = stories
             .filter_map(|story| story.epic_id)
@@ -198,14 +707,1167 @@ impl ShortcutClient {
         epics.sort_by_key(|epic| epic.id);
         Ok(epics)
     }
+
+    /// List the stories of each epic which are not part of the release
+    async fn get_epic_stories_not_in_release(
+        &self,
+        epics: &[Epic],
+        stories: &[Story],
+    ) -> Result<HashMap<i64, EpicStoriesNotInRelease>> {
+        let release_story_ids = stories.iter().map(|story| story.id).collect::<HashSet<_>>();
+        let epics_and_stories = self
+            .get_shortcut_data(epics.iter().map(|epic| {
+                let epic_id = epic.id;
+                shortcut_api::list_epic_stories(&self.configuration, epic_id, Some(false))
+                    .map_ok(move |epic_stories| (epic_id, epic_stories))
+                    .map_err(move |err| {
+                        anyhow!(
+                            "Error while retrieving stories of epic {}: {:?}",
+                            epic_id,
+                            err
+                        )
+                    })
+            }))
+            .await?;
+        Ok(epics_and_stories
+            .into_iter()
+            .map(|(epic_id, epic_stories)| {
+                let (mut done, mut unfinished): (Vec<_>, Vec<_>) = epic_stories
+                    .into_iter()
+                    .filter(|story| !story.archived && !release_story_ids.contains(&story.id))
+                    .partition(|story| story.completed);
+                done.sort_by_key(|story| story.id);
+                unfinished.sort_by_key(|story| story.id);
+                (epic_id, EpicStoriesNotInRelease { done, unfinished })
+            })
+            .collect())
+    }
+
+    /// Retrieve all the members of the organization, in a single request
+    async fn get_members(&self) -> Result<MemberIdToMember> {
+        self.rate_limiter.until_ready().await;
+        let members = shortcut_api::list_members(&self.configuration, None)
+            .await
+            .map_err(|err| anyhow!("Error while retrieving members: {:?}", err))?;
+        Ok(members
+            .into_iter()
+            .map(|member| (member.id.clone(), member))
+            .collect())
+    }
+
+    async fn get_workflows(&self) -> Result<Vec<Workflow>> {
+        self.rate_limiter.until_ready().await;
+        shortcut_api::list_workflows(&self.configuration)
+            .await
+            .map_err(|err| anyhow!("Error while retrieving workflows: {:?}", err))
+    }
+
+    /// Retrieve the states of all workflows, in a single request
+    async fn get_workflow_states(&self) -> Result<HashMap<i64, StoryState>> {
+        let workflows = self.get_workflows().await?;
+        Ok(workflows
+            .into_iter()
+            .flat_map(|workflow| workflow.states)
+            .map(|state| {
+                (
+                    state.id,
+                    StoryState {
+                        name: state.name,
+                        state_type: state.r#type,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Retrieve the custom field definitions, in a single request
+    async fn get_custom_fields(&self) -> Result<CustomFields> {
+        self.rate_limiter.until_ready().await;
+        let custom_fields = shortcut_api::list_custom_fields(&self.configuration)
+            .await
+            .map_err(|err| anyhow!("Error while retrieving custom fields: {:?}", err))?;
+        Ok(CustomFields(custom_fields))
+    }
+
+    /// Compute the updates needed for each story to reflect the changes. Stories which already
+    /// match the changes are skipped, so that applying the same changes twice is a no-op.
+    pub async fn plan_story_updates(
+        &self,
+        stories: &[ReleaseStory],
+        changes: &StoryChanges,
+    ) -> Result<Vec<StoryUpdate>> {
+        let workflows = if changes.workflow_state.is_some() {
+            self.get_workflows().await?
+        } else {
+            Vec::new()
+        };
+        let custom_field = match &changes.custom_field {
+            Some(custom_field) => Some((
+                custom_field,
+                self.get_custom_fields()
+                    .await?
+                    .find_value_ids(custom_field)?,
+            )),
+            None => None,
+        };
+        let mut updates = Vec::new();
+        for release_story in stories {
+            let story = &release_story.story;
+            let mut update = serde_json::Map::new();
+            let mut field_changes = Vec::new();
+            if let Some(label) = &changes.label {
+                if !story
+                    .labels
+                    .iter()
+                    .any(|story_label| &story_label.name == label)
+                {
+                    let labels = with_label(
+                        story
+                            .labels
+                            .iter()
+                            .map(|story_label| story_label.name.as_str()),
+                        label,
+                    );
+                    update.insert("labels".to_string(), labels);
+                    field_changes.push(FieldChange {
+                        field: "label".to_string(),
+                        before: None,
+                        after: label.clone(),
+                    });
+                }
+            }
+            if let Some(workflow_state) = &changes.workflow_state {
+                let state_id = workflows
+                    .iter()
+                    .filter(|workflow| workflow.id == story.workflow_id)
+                    .flat_map(|workflow| workflow.states.iter())
+                    .find(|state| &state.name == workflow_state)
+                    .map(|state| state.id)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Unknown workflow state {:?} for story {}",
+                            workflow_state,
+                            story.id
+                        )
+                    })?;
+                if story.workflow_state_id != state_id {
+                    update.insert("workflow_state_id".to_string(), json!(state_id));
+                    field_changes.push(FieldChange {
+                        field: "workflow state".to_string(),
+                        before: release_story
+                            .workflow_state
+                            .as_ref()
+                            .map(|state| state.name.clone()),
+                        after: workflow_state.clone(),
+                    });
+                }
+            }
+            if let Some((custom_field, (field_id, value_id))) = &custom_field {
+                let current_value = story
+                    .custom_fields
+                    .iter()
+                    .find(|story_field| &story_field.field_id == field_id);
+                if current_value.map_or(true, |current| &current.value_id != value_id) {
+                    // Custom field values are replaced as a whole by the API
+                    let custom_fields = story
+                        .custom_fields
+                        .iter()
+                        .filter(|story_field| &story_field.field_id != field_id)
+                        .map(|story_field| (&story_field.field_id, &story_field.value_id))
+                        .chain(iter::once((field_id, value_id)))
+                        .map(|(field_id, value_id)| {
+                            json!({ "field_id": field_id, "value_id": value_id })
+                        })
+                        .collect();
+                    update.insert(
+                        "custom_fields".to_string(),
+                        serde_json::Value::Array(custom_fields),
+                    );
+                    field_changes.push(FieldChange {
+                        field: custom_field.name.clone(),
+                        before: current_value.map(|current| current.value.clone()),
+                        after: custom_field.value.clone(),
+                    });
+                }
+            }
+            if !field_changes.is_empty() {
+                updates.push(StoryUpdate {
+                    story_id: story.id,
+                    story_name: story.name.clone(),
+                    changes: field_changes,
+                    update: request_body(serde_json::Value::Object(update))?,
+                });
+            }
+        }
+        Ok(updates)
+    }
+
+    pub async fn apply_story_updates(&self, updates: Vec<StoryUpdate>) -> Result<()> {
+        self.get_shortcut_data(updates.into_iter().map(|update| {
+            let story_id = update.story_id;
+            shortcut_api::update_story(&self.configuration, story_id, update.update)
+                .map_err(move |err| anyhow!("Error while updating story {}: {:?}", story_id, err))
+        }))
+        .await?;
+        Ok(())
+    }
+
+    /// Create or update the entity representing the release, with the release notes as
+    /// description, and link all stories of the release to it. Returns the URL of the entity.
+    pub async fn publish_release_entity(
+        &self,
+        entity: &ReleaseEntity,
+        release_notes: &str,
+        stories: &[ReleaseStory],
+    ) -> Result<String> {
+        match entity.kind {
+            ReleaseEntityKind::Epic => {
+                self.publish_release_epic(entity, release_notes, stories)
+                    .await
+            }
+            ReleaseEntityKind::Story => {
+                self.publish_release_story(entity, release_notes, stories)
+                    .await
+            }
+        }
+    }
+
+    async fn publish_release_epic(
+        &self,
+        entity: &ReleaseEntity,
+        release_notes: &str,
+        stories: &[ReleaseStory],
+    ) -> Result<String> {
+        self.rate_limiter.until_ready().await;
+        let existing_epic = shortcut_api::list_epics(&self.configuration, Some(false))
+            .await
+            .map_err(|err| anyhow!("Error while retrieving epics: {:?}", err))?
+            .into_iter()
+            .find(|epic| epic.external_id.as_deref() == Some(entity.external_id.as_str()));
+        self.rate_limiter.until_ready().await;
+        // Stories keep their own epic: they are linked to the release epic by a label named
+        // after it, which the epic has as well
+        let label = &entity.name;
+        let epic = if let Some(existing_epic) = existing_epic {
+            debug!("Updating release epic {}", existing_epic.id);
+            let update_epic = request_body(json!({
+                "name": entity.name,
+                "description": release_notes,
+                "labels": with_label(
+                    existing_epic.labels.iter().map(|epic_label| epic_label.name.as_str()),
+                    label,
+                ),
+            }))?;
+            shortcut_api::update_epic(&self.configuration, existing_epic.id, update_epic)
+                .await
+                .map_err(|err| {
+                    anyhow!("Error while updating epic {}: {:?}", existing_epic.id, err)
+                })?
+        } else {
+            debug!("Creating release epic {}", entity.name);
+            let create_epic = request_body(json!({
+                "name": entity.name,
+                "description": release_notes,
+                "external_id": entity.external_id,
+                "labels": [{ "name": label }],
+            }))?;
+            shortcut_api::create_epic(&self.configuration, create_epic)
+                .await
+                .map_err(|err| anyhow!("Error while creating epic: {:?}", err))?
+        };
+        let unlabeled_stories = stories.iter().filter(|release_story| {
+            !release_story
+                .story
+                .labels
+                .iter()
+                .any(|story_label| &story_label.name == label)
+        });
+        self.get_shortcut_data(unlabeled_stories.map(|release_story| {
+            let story = &release_story.story;
+            let story_id = story.id;
+            let update_story: Result<UpdateStory> = request_body(json!({
+                "labels": with_label(
+                    story.labels.iter().map(|story_label| story_label.name.as_str()),
+                    label,
+                ),
+            }));
+            async move {
+                shortcut_api::update_story(&self.configuration, story_id, update_story?)
+                    .await
+                    .map_err(|err| anyhow!("Error while updating story {}: {:?}", story_id, err))
+            }
+        }))
+        .await?;
+        Ok(epic.app_url)
+    }
+
+    async fn publish_release_story(
+        &self,
+        entity: &ReleaseEntity,
+        release_notes: &str,
+        stories: &[ReleaseStory],
+    ) -> Result<String> {
+        self.rate_limiter.until_ready().await;
+        let search_stories = request_body(json!({ "external_id": entity.external_id }))?;
+        let existing_story = shortcut_api::query_stories(&self.configuration, search_stories)
+            .await
+            .map_err(|err| anyhow!("Error while searching stories: {:?}", err))?
+            .into_iter()
+            .next();
+        let release_story = if let Some(existing_story) = existing_story {
+            debug!("Updating release story {}", existing_story.id);
+            let update_story = request_body(json!({
+                "name": entity.name,
+                "description": release_notes,
+            }))?;
+            self.rate_limiter.until_ready().await;
+            shortcut_api::update_story(&self.configuration, existing_story.id, update_story)
+                .await
+                .map_err(|err| {
+                    anyhow!(
+                        "Error while updating story {}: {:?}",
+                        existing_story.id,
+                        err
+                    )
+                })?
+        } else {
+            debug!("Creating release story {}", entity.name);
+            let workflow_state = entity.workflow_state.as_ref().ok_or_else(|| {
+                anyhow!("A workflow_state is required to create the release story")
+            })?;
+            let workflow_state_id = self
+                .get_workflows()
+                .await?
+                .iter()
+                .flat_map(|workflow| workflow.states.iter())
+                .find(|state| &state.name == workflow_state)
+                .map(|state| state.id)
+                .ok_or_else(|| anyhow!("Unknown workflow state {:?}", workflow_state))?;
+            let create_story_params = request_body(json!({
+                "name": entity.name,
+                "description": release_notes,
+                "external_id": entity.external_id,
+                "story_type": "chore",
+                "workflow_state_id": workflow_state_id,
+            }))?;
+            self.rate_limiter.until_ready().await;
+            shortcut_api::create_story(&self.configuration, create_story_params)
+                .await
+                .map_err(|err| anyhow!("Error while creating story: {:?}", err))?
+        };
+        let release_story_id = release_story.id;
+        let linked_story_ids = release_story
+            .story_links
+            .iter()
+            .flat_map(|link| [link.subject_id, link.object_id])
+            .collect::<HashSet<_>>();
+        let unlinked_stories = stories
+            .iter()
+            .filter(|story| !linked_story_ids.contains(&story.story.id));
+        self.get_shortcut_data(unlinked_stories.map(|story| {
+            let story_id = story.story.id;
+            let create_story_link_params = request_body(json!({
+                "subject_id": release_story_id,
+                "object_id": story_id,
+                "verb": "relates to",
+            }));
+            async move {
+                shortcut_api::create_story_link(&self.configuration, create_story_link_params?)
+                    .await
+                    .map_err(|err| anyhow!("Error while linking story {}: {:?}", story_id, err))
+            }
+        }))
+        .await?;
+        Ok(release_story.app_url)
+    }
+
+    async fn get_iterations(
+        &self,
+        stories: impl Iterator<Item = &Story>,
+    ) -> Result<Vec<Iteration>> {
+        let iteration_ids = stories
+            .filter_map(|story| story.iteration_id)
+            .collect::<HashSet<_>>();
+        let mut iterations = self
+            .get_shortcut_data(iteration_ids.into_iter().map(|iteration_id| {
+                shortcut_api::get_iteration(&self.configuration, iteration_id).map_err(move |err| {
+                    anyhow!(
+                        "Error while retrieving iteration {}: {:?}",
+                        iteration_id,
+                        err
+                    )
+                })
+            }))
+            .await?;
+        iterations.sort_by_key(|iteration| iteration.id);
+        Ok(iterations)
+    }
+
+    async fn get_groups(&self, stories: impl Iterator<Item = &Story>) -> Result<Vec<Group>> {
+        let group_ids = stories
+            .filter_map(|story| story.group_id.as_ref())
+            .collect::<HashSet<_>>();
+        let mut groups = self
+            .get_shortcut_data(group_ids.into_iter().map(|group_id| {
+                shortcut_api::get_group(&self.configuration, group_id).map_err(move |err| {
+                    anyhow!("Error while retrieving group {}: {:?}", group_id, err)
+                })
+            }))
+            .await?;
+        groups.sort_by(|group1, group2| group1.name.cmp(&group2.name));
+        Ok(groups)
+    }
+
+    async fn get_objectives(&self, epics: impl Iterator<Item = &Epic>) -> Result<Vec<Objective>> {
+        let objective_ids = epics
+            .flat_map(|epic| epic.objective_ids.iter().copied())
+            .collect::<HashSet<_>>();
+        let mut objectives = self
+            .get_shortcut_data(objective_ids.into_iter().map(|objective_id| {
+                shortcut_api::get_objective(&self.configuration, objective_id).map_err(move |err| {
+                    anyhow!(
+                        "Error while retrieving objective {}: {:?}",
+                        objective_id,
+                        err
+                    )
+                })
+            }))
+            .await?;
+        objectives.sort_by_key(|objective| objective.id);
+        Ok(objectives)
+    }
+}
+
+/// Extract the `next` parameter from the `next` URL returned by the search endpoint. The
+/// parameter is decoded, as the API client encodes it again.
+fn next_page_token(next_url: &str) -> Option<String> {
+    let (_, query) = next_url.split_once('?')?;
+    form_urlencoded::parse(query.as_bytes())
+        .find(|(name, _)| name == "next")
+        .map(|(_, token)| token.into_owned())
+        .filter(|token| !token.is_empty())
+}
+
+/// Label names along with the given label, in the form expected by the API, which replaces
+/// the labels of a story or epic as a whole
+fn with_label<'a>(label_names: impl Iterator<Item = &'a str>, label: &str) -> serde_json::Value {
+    label_names
+        .filter(|name| *name != label)
+        .chain(iter::once(label))
+        .map(|name| json!({ "name": name }))
+        .collect()
+}
+
+/// Build an API request body from its JSON representation, which only requires the fields
+/// actually sent to be listed
+fn request_body<T: DeserializeOwned>(body: serde_json::Value) -> Result<T> {
+    Ok(serde_json::from_value(body)?)
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, AsRef, FromStr, Display, Into)]
 pub struct StoryId(u32);
 
+/// A member id (UUID) -> member mapping
+pub type MemberIdToMember = HashMap<String, Member>;
+
+/// Name of a member, falling back to their mention name if the name is not set
+pub fn member_name(member: &Member) -> &str {
+    member
+        .profile
+        .name
+        .as_deref()
+        .unwrap_or(&member.profile.mention_name)
+}
+
+/// Workflow state of a story
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct StoryState {
+    /// Human-readable name of the state, e.g. `In Review`
+    pub name: String,
+    /// Type of the state, one of `unstarted`, `started` or `done`
+    #[serde(rename = "type")]
+    pub state_type: String,
+}
+
+impl StoryState {
+    pub fn is_done(&self) -> bool {
+        self.state_type == "done"
+    }
+}
+
+/// A story of the release, along with the data resolved from other Shortcut entities
+#[derive(Debug, Clone)]
+pub struct ReleaseStory {
+    pub story: Story,
+    /// Workflow state of the story, `None` if the state could not be found in the workflows
+    pub workflow_state: Option<StoryState>,
+    /// Values of the custom fields of the story, keyed by field name
+    pub custom_fields: BTreeMap<String, String>,
+    /// Stories linked to the story, empty unless story links are included
+    pub linked_stories: Vec<LinkedStory>,
+    /// Stories duplicating this story, which were folded into it
+    pub folded_duplicates: Vec<LinkedStory>,
+    /// Release note found in the comments of the story, if release notes are extracted
+    pub release_note: Option<String>,
+    /// Commits of the story in the release, by repository. Serialized as `release_commits`, as
+    /// the story has its own `commits` field, listing its VCS commits.
+    pub release_commits: RepoToCommits,
+}
+
+/// Fields of a [`ReleaseStory`] added to the story fields when serializing it
+#[derive(Serialize, JsonSchema)]
+struct ResolvedStoryFields<'a> {
+    workflow_state: &'a Option<StoryState>,
+    custom_fields: &'a BTreeMap<String, String>,
+    linked_stories: &'a [LinkedStory],
+    folded_duplicates: &'a [LinkedStory],
+    release_note: &'a Option<String>,
+    release_commits: &'a RepoToCommits,
+}
+
+/// Schema of a serialized [`ReleaseStory`]
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct ReleaseStorySchema {
+    #[serde(flatten)]
+    story: ShortcutEntity,
+    #[serde(flatten)]
+    resolved_fields: ResolvedStoryFields<'static>,
+}
+
+impl JsonSchema for ReleaseStory {
+    fn schema_name() -> String {
+        "ReleaseStory".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        ReleaseStorySchema::json_schema(gen)
+    }
+}
+
+/// A story linked to another story
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct LinkedStory {
+    pub id: i64,
+    pub name: String,
+    pub app_url: String,
+    /// Relation of the story to the linked story, i.e. `blocks`, `is blocked by`, `duplicates`,
+    /// `is duplicated by` or `relates to`
+    pub relation: String,
+}
+
+impl LinkedStory {
+    fn new(story: &Story, relation: impl Into<String>) -> Self {
+        Self {
+            id: story.id,
+            name: story.name.clone(),
+            app_url: story.app_url.clone(),
+            relation: relation.into(),
+        }
+    }
+}
+
+/// Inverse of a story link verb, i.e. the relation from the point of view of the link object
+fn inverse_link_verb(verb: &str) -> String {
+    match verb {
+        "blocks" => "is blocked by".to_owned(),
+        "duplicates" => "is duplicated by".to_owned(),
+        verb => verb.to_owned(),
+    }
+}
+
+/// Resolve the links of a story, looking up the linked stories in the release stories then in
+/// the retrieved linked stories
+fn resolve_story_links(
+    story: &Story,
+    release_stories: &[Story],
+    linked_stories: &HashMap<i64, Story>,
+) -> Vec<LinkedStory> {
+    story
+        .story_links
+        .iter()
+        .filter_map(|link| {
+            let (linked_story_id, relation) = if link.subject_id == story.id {
+                (link.object_id, link.verb.clone())
+            } else {
+                (link.subject_id, inverse_link_verb(&link.verb))
+            };
+            let linked_story = release_stories
+                .iter()
+                .find(|release_story| release_story.id == linked_story_id)
+                .or_else(|| linked_stories.get(&linked_story_id));
+            if linked_story.is_none() {
+                warn!(
+                    "Story {} is linked to story {} which could not be found",
+                    story.id, linked_story_id
+                );
+            }
+            linked_story.map(|linked_story| LinkedStory::new(linked_story, relation))
+        })
+        .collect()
+}
+
+/// Release note of a story, taken from its latest comment containing a line starting with the
+/// marker: the rest of this line and the following lines of the comment
+fn story_release_note(story: &Story, marker: &str) -> Option<String> {
+    story
+        .comments
+        .iter()
+        .rev()
+        .filter(|comment| !comment.deleted)
+        .find_map(|comment| extract_release_note(comment.text.as_deref()?, marker))
+}
+
+fn extract_release_note(text: &str, marker: &str) -> Option<String> {
+    let mut lines = text.lines();
+    let first_line = lines.by_ref().map(str::trim_start).find(|line| {
+        line.get(..marker.len())
+            .map_or(false, |start| start.eq_ignore_ascii_case(marker))
+    })?;
+    let release_note = iter::once(&first_line[marker.len()..])
+        .chain(lines)
+        .join("\n");
+    let release_note = release_note.trim();
+    (!release_note.is_empty()).then(|| release_note.to_owned())
+}
+
+/// Replace the stories duplicating another story (the canonical story) by the latter, adding
+/// the canonical story to the release if needed. Returns the folded stories by canonical story
+/// id. Stories duplicating each other, or whose canonical story could not be found, are kept.
+fn fold_duplicates(
+    stories: &mut Vec<Story>,
+    linked_stories: &HashMap<i64, Story>,
+) -> HashMap<i64, Vec<LinkedStory>> {
+    let canonical_story_id = |story: &Story| {
+        story
+            .story_links
+            .iter()
+            .find(|link| link.verb == "duplicates" && link.subject_id == story.id)
+            .map(|link| link.object_id)
+    };
+    let release_story_ids = stories.iter().map(|story| story.id).collect::<HashSet<_>>();
+    let duplicate_story_ids = stories
+        .iter()
+        .filter(|story| canonical_story_id(story).is_some())
+        .map(|story| story.id)
+        .collect::<HashSet<_>>();
+    let mut folded_duplicates: HashMap<i64, Vec<LinkedStory>> = HashMap::new();
+    stories.retain(|story| {
+        let canonical_id = canonical_story_id(story).filter(|canonical_id| {
+            !duplicate_story_ids.contains(canonical_id)
+                && (release_story_ids.contains(canonical_id)
+                    || linked_stories.contains_key(canonical_id))
+        });
+        match canonical_id {
+            Some(canonical_id) => {
+                folded_duplicates
+                    .entry(canonical_id)
+                    .or_default()
+                    .push(LinkedStory::new(story, "is duplicated by"));
+                false
+            }
+            None => true,
+        }
+    });
+    for canonical_id in folded_duplicates.keys() {
+        if !release_story_ids.contains(canonical_id) {
+            stories.push(linked_stories[canonical_id].clone());
+        }
+    }
+    stories.sort_by_key(|story| story.id);
+    folded_duplicates
+}
+
+/// Story fields replaced by the resolved field of the same name when serializing a
+/// [`ReleaseStory`]. Any other resolved field must not collide with a story field.
+const REPLACED_STORY_FIELDS: &[&str] = &["custom_fields"];
+
+impl Serialize for ReleaseStory {
+    /// Serialize the story fields along with the resolved fields, the latter replacing the
+    /// [`REPLACED_STORY_FIELDS`]
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut fields = match serde_json::to_value(&self.story).map_err(ser::Error::custom)? {
+            serde_json::Value::Object(fields) => fields,
+            _ => return Err(ser::Error::custom("a story should be serialized as a map")),
+        };
+        let resolved_fields = ResolvedStoryFields {
+            workflow_state: &self.workflow_state,
+            custom_fields: &self.custom_fields,
+            linked_stories: &self.linked_stories,
+            folded_duplicates: &self.folded_duplicates,
+            release_note: &self.release_note,
+            release_commits: &self.release_commits,
+        };
+        if let serde_json::Value::Object(resolved_fields) =
+            serde_json::to_value(resolved_fields).map_err(ser::Error::custom)?
+        {
+            for (name, value) in resolved_fields {
+                if fields.contains_key(&name) && !REPLACED_STORY_FIELDS.contains(&name.as_str()) {
+                    return Err(ser::Error::custom(format!(
+                        "the resolved field {} would replace the story field of the same name",
+                        name
+                    )));
+                }
+                fields.insert(name, value);
+            }
+        }
+        fields.serialize(serializer)
+    }
+}
+
+/// Shortcut entity representing the release
+#[derive(Debug)]
+pub struct ReleaseEntity {
+    pub kind: ReleaseEntityKind,
+    pub name: String,
+    /// Identifier of the release, used to find the entity created by a previous run
+    pub external_id: String,
+    /// Name of the workflow state, used when creating a story
+    pub workflow_state: Option<String>,
+}
+
+/// A change made to a field of a story, for display purposes
+#[derive(Debug)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Option<String>,
+    pub after: String,
+}
+
+/// Update to send to Shortcut for a story
+#[derive(Debug)]
+pub struct StoryUpdate {
+    pub story_id: i64,
+    pub story_name: String,
+    pub changes: Vec<FieldChange>,
+    update: UpdateStory,
+}
+
+/// Stories of an epic which are not part of the release
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct EpicStoriesNotInRelease {
+    /// Completed stories, either released previously or without any commit in the release
+    #[schemars(with = "Vec<ShortcutEntity>")]
+    pub done: Vec<StorySlim>,
+    /// Stories which are not completed yet
+    #[schemars(with = "Vec<ShortcutEntity>")]
+    pub unfinished: Vec<StorySlim>,
+}
+
+/// An epic of the release, along with the data resolved from other Shortcut entities
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ReleaseEpic {
+    #[serde(flatten)]
+    #[schemars(with = "ShortcutEntity")]
+    pub epic: Epic,
+    /// Stories of the epic missing from the release, `None` unless
+    /// [`ReleaseOptions::include_epic_stories`] is set
+    pub stories_not_in_release: Option<EpicStoriesNotInRelease>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ReleaseContent {
-    pub stories: Vec<Story>,
-    pub epics: Vec<Epic>,
+    pub stories: Vec<ReleaseStory>,
+    pub epics: Vec<ReleaseEpic>,
+    pub iterations: Vec<Iteration>,
+    pub groups: Vec<Group>,
+    pub objectives: Vec<Objective>,
+    pub members: MemberIdToMember,
     pub unparsed_commits: RepoToCommits,
+    /// Custom field definitions, used to filter the stories further
+    #[serde(skip)]
+    pub custom_fields: CustomFields,
+    /// Workflow states by id, used to filter the stories further
+    #[serde(skip)]
+    pub workflow_states: HashMap<i64, StoryState>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Path and `next` parameter of a request received by the stand-in server
+    type Request = (String, Option<String>);
+
+    /// Start a stand-in for the Shortcut API, answering each request with the JSON returned by
+    /// the handler for its path and query parameters (404 for `null`). Returns the base path of
+    /// the server, along with the requests it received.
+    async fn stand_in_server(
+        handler: fn(&str, &HashMap<String, String>) -> serde_json::Value,
+    ) -> (String, Arc<Mutex<Vec<Request>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_path = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received_requests = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    let read = socket.read(&mut buffer).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let request = String::from_utf8_lossy(&request);
+                let target = request.split(' ').nth(1).unwrap_or_default();
+                let (path, query) = target.split_once('?').unwrap_or((target, ""));
+                let params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+                    .into_owned()
+                    .collect();
+                received_requests
+                    .lock()
+                    .unwrap()
+                    .push((path.to_owned(), params.get("next").cloned()));
+                let body = handler(path, &params);
+                let status = if body.is_null() {
+                    "404 Not Found"
+                } else {
+                    "200 OK"
+                };
+                let body = body.to_string();
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (base_path, requests)
+    }
+
+    /// A story with the fields required by the story schema
+    const STORY: &str = r#"{
+        "app_url": "https://app.shortcut.com/acme/story/1",
+        "archived": false,
+        "blocked": false,
+        "blocker": false,
+        "branches": [],
+        "comment_ids": [],
+        "comments": [],
+        "commits": [],
+        "completed": true,
+        "completed_at": "2023-09-20T15:30:00Z",
+        "created_at": "2023-09-04T09:12:00Z",
+        "custom_fields": [],
+        "cycle_time": 0,
+        "description": "",
+        "entity_type": "story",
+        "external_links": [],
+        "file_ids": [],
+        "files": [],
+        "follower_ids": [],
+        "global_id": "v2:s:1:1",
+        "group_mention_ids": [],
+        "id": 1,
+        "label_ids": [],
+        "labels": [],
+        "lead_time": 0,
+        "linked_file_ids": [],
+        "linked_files": [],
+        "member_mention_ids": [],
+        "mention_ids": [],
+        "moved_at": "2023-09-20T15:30:00Z",
+        "name": "Story 1",
+        "num_tasks_completed": 0,
+        "owner_ids": [],
+        "position": 1,
+        "previous_iteration_ids": [],
+        "pull_requests": [],
+        "requested_by_id": "5f0c6e35-0000-4000-8000-000000000101",
+        "started": true,
+        "started_at": "2023-09-11T08:00:00Z",
+        "stats": { "num_related_documents": 0 },
+        "story_links": [],
+        "story_type": "feature",
+        "sub_task_story_ids": [],
+        "task_ids": [],
+        "tasks": [],
+        "unresolved_blocker_comments": [],
+        "updated_at": "2023-09-20T15:30:00Z",
+        "workflow_id": 500000001,
+        "workflow_state_id": 500000010
+    }"#;
+
+    /// [`STORY`], with the given id
+    fn story(id: i64) -> serde_json::Value {
+        let mut story: serde_json::Value = serde_json::from_str(STORY).unwrap();
+        story["id"] = id.into();
+        story["name"] = format!("Story {}", id).into();
+        story["app_url"] = format!("https://app.shortcut.com/acme/story/{}", id).into();
+        story
+    }
+
+    /// Search results spread over two pages, with an unrequested story, and without story 3
+    fn search_api(path: &str, params: &HashMap<String, String>) -> serde_json::Value {
+        match (path, params.get("next").map(String::as_str)) {
+            ("/api/v3/search/stories", None) => json!({
+                "data": [story(1), story(99)],
+                "next": "/api/v3/search/stories?query=id%3A1%2C2%2C3&page_size=25&detail=full\
+                         &next=a%2Bb%3D%3D",
+                "total": 3,
+            }),
+            ("/api/v3/search/stories", Some("a+b==")) => json!({
+                "data": [story(2)],
+                "next": null,
+                "total": 3,
+            }),
+            ("/api/v3/stories/3", _) => story(3),
+            _ => serde_json::Value::Null,
+        }
+    }
+
+    #[tokio::test]
+    async fn get_stories_follows_search_pages_and_fetches_missing_stories() {
+        let (base_path, requests) = stand_in_server(search_api).await;
+        let client =
+            ShortcutClient::new(&ShortcutApiKey::new("token".to_owned())).with_base_path(base_path);
+
+        let stories = client
+            .get_stories(vec![StoryId(1), StoryId(2), StoryId(3)])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            stories.iter().map(|story| story.id).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                ("/api/v3/search/stories".to_owned(), None),
+                (
+                    "/api/v3/search/stories".to_owned(),
+                    Some("a+b==".to_owned())
+                ),
+                ("/api/v3/stories/3".to_owned(), None),
+            ]
+        );
+    }
+
+    /// A pull request of the `acme/<repository>` GitHub repository
+    fn pull_request(repository: &str, number: i64, branch_name: &str) -> serde_json::Value {
+        json!({
+            "branch_id": number,
+            "branch_name": branch_name,
+            "closed": true,
+            "created_at": "2023-09-12T10:00:00Z",
+            "draft": false,
+            "entity_type": "pull-request",
+            "has_overlapping_stories": false,
+            "id": number,
+            "merged": true,
+            "num_added": 1,
+            "num_commits": 1,
+            "num_modified_files": 1,
+            "num_removed": 1,
+            "number": number,
+            "repository_id": 1,
+            "target_branch_id": 1,
+            "target_branch_name": "main",
+            "title": format!("Pull request {}", number),
+            "updated_at": "2023-09-12T10:00:00Z",
+            "url": format!("https://github.com/acme/{}/pull/{}", repository, number),
+        })
+    }
+
+    /// [`STORY`], with a commit, a branch and a pull request of `acme/app`, along with a pull
+    /// request of `acme/website`
+    fn vcs_story() -> Story {
+        let mut story = story(1);
+        story["commits"] = json!([{
+            "author_email": "jdoe@acme.com",
+            "author_id": null,
+            "author_identity": { "entity_type": "identity", "name": "jdoe", "type": "github" },
+            "created_at": "2023-09-12T10:00:00Z",
+            "entity_type": "commit",
+            "hash": "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3",
+            "id": 1,
+            "merged_branch_ids": [],
+            "message": "Fix the login form",
+            "repository_id": 1,
+            "timestamp": "2023-09-12T10:00:00Z",
+            "updated_at": "2023-09-12T10:00:00Z",
+            "url": "https://github.com/acme/app/commit/a94a8fe5ccb19ba61c4c0873d391e987982fbbd3",
+        }]);
+        story["branches"] = json!([{
+            "created_at": "2023-09-12T10:00:00Z",
+            "deleted": false,
+            "entity_type": "branch",
+            "id": 1,
+            "merged_branch_ids": [],
+            "name": "sc-1/fix-login",
+            "persistent": false,
+            "pull_requests": [],
+            "repository_id": 1,
+            "updated_at": "2023-09-12T10:00:00Z",
+            "url": "https://github.com/acme/app/tree/sc-1/fix-login",
+        }]);
+        story["pull_requests"] = json!([
+            pull_request("app", 12, "fix-login-form"),
+            pull_request("website", 34, "fix-login-page"),
+        ]);
+        serde_json::from_value(story).unwrap()
+    }
+
+    /// A commit not yet linked to a story
+    fn unreleased_commit(id: &str, message: &str) -> UnreleasedCommit {
+        UnreleasedCommit {
+            id: git2::Oid::from_str(id).unwrap(),
+            message: Some(message.to_owned()),
+            committed_at: chrono::DateTime::parse_from_rfc3339("2023-09-12T10:00:00Z").unwrap(),
+        }
+    }
+
+    const APP: Option<&str> = Some("github.com/acme/app");
+    const WEBSITE: Option<&str> = Some("github.com/acme/website");
+
+    #[test]
+    fn vcs_commit_is_matched_by_sha() {
+        let commit = unreleased_commit("a94a8fe5ccb19ba61c4c0873d391e987982fbbd3", "Fix the login");
+
+        assert!(is_vcs_commit_of_story(&vcs_story(), &commit, APP));
+        assert!(is_vcs_commit_of_story(&vcs_story(), &commit, None));
+    }
+
+    #[test]
+    fn vcs_commit_is_matched_by_squashed_pull_request_of_its_repository() {
+        let commit = unreleased_commit(
+            "1111111111111111111111111111111111111111",
+            "Fix the login form (#12)",
+        );
+        let website_commit = unreleased_commit(
+            "2222222222222222222222222222222222222222",
+            "Fix the login page (#34)",
+        );
+
+        assert!(is_vcs_commit_of_story(&vcs_story(), &commit, APP));
+        assert!(!is_vcs_commit_of_story(&vcs_story(), &commit, WEBSITE));
+        assert!(!is_vcs_commit_of_story(&vcs_story(), &commit, None));
+        assert!(is_vcs_commit_of_story(
+            &vcs_story(),
+            &website_commit,
+            WEBSITE
+        ));
+        assert!(!is_vcs_commit_of_story(&vcs_story(), &website_commit, APP));
+    }
+
+    #[test]
+    fn vcs_commit_is_matched_by_merged_pull_request_of_its_repository() {
+        let commit = unreleased_commit(
+            "1111111111111111111111111111111111111111",
+            "Merge pull request #12 from acme/fix-login-form\n\nFix login",
+        );
+
+        assert!(is_vcs_commit_of_story(&vcs_story(), &commit, APP));
+        assert!(!is_vcs_commit_of_story(&vcs_story(), &commit, WEBSITE));
+    }
+
+    #[test]
+    fn vcs_commit_is_matched_by_merged_branch_of_its_repository() {
+        let branch_commit = unreleased_commit(
+            "1111111111111111111111111111111111111111",
+            "Merge branch 'sc-1/fix-login' into main",
+        );
+        let pull_request_branch_commit = unreleased_commit(
+            "2222222222222222222222222222222222222222",
+            "Merge branch 'fix-login-page'",
+        );
+
+        assert!(is_vcs_commit_of_story(&vcs_story(), &branch_commit, APP));
+        assert!(!is_vcs_commit_of_story(
+            &vcs_story(),
+            &branch_commit,
+            WEBSITE
+        ));
+        assert!(is_vcs_commit_of_story(
+            &vcs_story(),
+            &pull_request_branch_commit,
+            WEBSITE
+        ));
+        assert!(!is_vcs_commit_of_story(
+            &vcs_story(),
+            &pull_request_branch_commit,
+            APP
+        ));
+    }
+
+    /// Search results with story 1, story 2 being deleted
+    fn deleted_story_api(path: &str, _params: &HashMap<String, String>) -> serde_json::Value {
+        match path {
+            "/api/v3/search/stories" => json!({ "data": [story(1)], "next": null, "total": 1 }),
+            _ => serde_json::Value::Null,
+        }
+    }
+
+    #[tokio::test]
+    async fn get_stories_leniently_skips_the_stories_not_found() {
+        let (base_path, requests) = stand_in_server(deleted_story_api).await;
+        let client =
+            ShortcutClient::new(&ShortcutApiKey::new("token".to_owned())).with_base_path(base_path);
+
+        let stories = client
+            .get_stories_leniently(vec![StoryId(1), StoryId(2)])
+            .await;
+
+        assert_eq!(
+            stories.iter().map(|story| story.id).collect::<Vec<_>>(),
+            [1]
+        );
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                ("/api/v3/search/stories".to_owned(), None),
+                ("/api/v3/stories/2".to_owned(), None),
+            ]
+        );
+        assert!(client
+            .get_stories(vec![StoryId(1), StoryId(2)])
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn release_story_keeps_the_story_commits() {
+        let release_story = ReleaseStory {
+            story: serde_json::from_value(story(1)).unwrap(),
+            workflow_state: None,
+            custom_fields: BTreeMap::from([("Product area".to_owned(), "Billing".to_owned())]),
+            linked_stories: Vec::new(),
+            folded_duplicates: Vec::new(),
+            release_note: None,
+            release_commits: RepoToCommits::new(),
+        };
+
+        let fields = serde_json::to_value(&release_story).unwrap();
+
+        assert_eq!(fields["commits"], json!([]));
+        assert_eq!(fields["release_commits"], json!({}));
+        assert_eq!(
+            fields["custom_fields"],
+            json!({ "Product area": "Billing" })
+        );
+    }
+
+    #[test]
+    fn next_page_token_is_decoded() {
+        assert_eq!(
+            next_page_token("/api/v3/search/stories?query=id%3A1&next=a%2Bb%3D%3D").as_deref(),
+            Some("a+b==")
+        );
+        assert_eq!(next_page_token("/api/v3/search/stories?query=id%3A1"), None);
+        assert_eq!(next_page_token("/api/v3/search/stories?next="), None);
+    }
 }
@@ -0,0 +1,66 @@
+//! Templates embedded in the binary, selected with `builtin:<name>` instead of a template path.
+use anyhow::{anyhow, Result};
+
+use super::EscapeMode;
+
+/// Prefix of the built-in template names in the configuration
+pub const BUILTIN_PREFIX: &str = "builtin:";
+
+pub struct BuiltinTemplate {
+    pub name: &'static str,
+    /// Default file name when dumping the template
+    pub file_name: &'static str,
+    pub content: &'static str,
+    /// Auto-escape mode of the template, unless overridden by the output
+    pub escape: EscapeMode,
+}
+
+const BUILTIN_TEMPLATES: &[BuiltinTemplate] = &[
+    BuiltinTemplate {
+        name: "markdown-default",
+        file_name: "markdown-default.md.jinja",
+        content: include_str!("builtin/markdown-default.md.jinja"),
+        escape: EscapeMode::Markdown,
+    },
+    BuiltinTemplate {
+        name: "markdown-by-epic",
+        file_name: "markdown-by-epic.md.jinja",
+        content: include_str!("builtin/markdown-by-epic.md.jinja"),
+        escape: EscapeMode::Markdown,
+    },
+    BuiltinTemplate {
+        name: "keep-a-changelog",
+        file_name: "keep-a-changelog.md.jinja",
+        content: include_str!("builtin/keep-a-changelog.md.jinja"),
+        escape: EscapeMode::Markdown,
+    },
+    BuiltinTemplate {
+        name: "slack",
+        file_name: "slack.txt.jinja",
+        content: include_str!("builtin/slack.txt.jinja"),
+        escape: EscapeMode::Slack,
+    },
+    BuiltinTemplate {
+        name: "html",
+        file_name: "html.html.jinja",
+        content: include_str!("builtin/html.html.jinja"),
+        escape: EscapeMode::Html,
+    },
+];
+
+pub fn builtin_template(name: &str) -> Result<&'static BuiltinTemplate> {
+    BUILTIN_TEMPLATES
+        .iter()
+        .find(|template| template.name == name)
+        .ok_or_else(|| {
+            anyhow!(
+                "Unknown built-in template {:?}, expected one of: {}",
+                name,
+                BUILTIN_TEMPLATES
+                    .iter()
+                    .map(|template| template.name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+}
@@ -0,0 +1,332 @@
+//! Static checks of a template source, finding the filters and functions it uses, along with
+//! their line numbers. The template environment then tells which of them are unknown.
+use std::{collections::HashSet, fmt};
+
+/// Keywords of the template syntax which may be followed by an opening parenthesis
+const KEYWORDS: &[&str] = &[
+    "and",
+    "or",
+    "not",
+    "in",
+    "is",
+    "if",
+    "elif",
+    "else",
+    "for",
+    "recursive",
+    "with",
+    "block",
+    "extends",
+    "include",
+    "import",
+    "from",
+    "as",
+    "set",
+    "call",
+    "filter",
+    "macro",
+    "true",
+    "false",
+    "none",
+    "True",
+    "False",
+    "None",
+];
+
+/// Names callable in any template without being functions of the environment
+const SPECIAL_CALLABLES: &[&str] = &["caller", "super", "loop", "self", "varargs", "kwargs"];
+
+/// Kind of a name used by a template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameKind {
+    Filter,
+    Function,
+}
+
+impl fmt::Display for NameKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Filter => write!(f, "filter"),
+            Self::Function => write!(f, "function"),
+        }
+    }
+}
+
+/// A filter or function used by a template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsedName {
+    pub kind: NameKind,
+    pub name: String,
+    /// Line of the first use of the name, starting at 1
+    pub line: usize,
+}
+
+impl fmt::Display for UsedName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: unknown {} `{}`",
+            self.line, self.kind, self.name
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token<'s> {
+    Identifier(&'s str),
+    Symbol(char),
+}
+
+/// Tokens of a tag or expression, along with their offset in the tag, skipping string literals
+fn tokenize(tag: &str) -> Vec<(usize, Token<'_>)> {
+    let mut tokens = Vec::new();
+    let mut chars = tag.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c == '"' || c == '\'' {
+            // Skip the string literal, along with its escaped characters
+            while let Some((_, next)) = chars.next() {
+                match next {
+                    '\\' => {
+                        chars.next();
+                    }
+                    next if next == c => break,
+                    _ => {}
+                }
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start + c.len_utf8();
+            while let Some(&(index, next)) = chars.peek() {
+                if !(next.is_alphanumeric() || next == '_') {
+                    break;
+                }
+                end = index + next.len_utf8();
+                chars.next();
+            }
+            tokens.push((start, Token::Identifier(&tag[start..end])));
+        } else if !c.is_whitespace() {
+            tokens.push((start, Token::Symbol(c)));
+        }
+    }
+    tokens
+}
+
+/// Tags (`{% ... %}`) and expressions (`{{ ... }}`) of the template, along with their offset in
+/// the source, skipping comments
+fn tags(source: &str) -> Vec<(usize, &str)> {
+    let mut tags = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = source[offset..].find('{').map(|index| offset + index) {
+        let end_delimiter = match source[start + 1..].chars().next() {
+            Some('{') => "}}",
+            Some('%') => "%}",
+            Some('#') => "#}",
+            _ => {
+                offset = start + 1;
+                continue;
+            }
+        };
+        let content_start = start + 2;
+        let Some(content_end) = source[content_start..]
+            .find(end_delimiter)
+            .map(|index| content_start + index)
+        else {
+            break;
+        };
+        if end_delimiter != "#}" {
+            tags.push((content_start, &source[content_start..content_end]));
+        }
+        offset = content_end + end_delimiter.len();
+    }
+    tags
+}
+
+/// Filters and functions used by the template, in order of first use. Names defined by the
+/// template itself (macros, imports and variables) are not reported as functions.
+pub fn used_names(source: &str) -> Vec<UsedName> {
+    let line = |offset: usize| source[..offset].matches('\n').count() + 1;
+    let mut defined = HashSet::new();
+    let mut calls = Vec::new();
+    let mut used_names: Vec<UsedName> = Vec::new();
+    let add = |used_names: &mut Vec<UsedName>, kind, name: &str, offset| {
+        if !used_names
+            .iter()
+            .any(|used_name| used_name.kind == kind && used_name.name == name)
+        {
+            used_names.push(UsedName {
+                kind,
+                name: name.to_owned(),
+                line: line(offset),
+            });
+        }
+    };
+    for (tag_offset, tag) in tags(source) {
+        let tokens = tokenize(tag.trim_start_matches(['-', '+']));
+        let tag_offset = tag_offset + tag.len() - tag.trim_start_matches(['-', '+']).len();
+        let first_identifier = match tokens.first() {
+            Some((_, Token::Identifier(identifier))) => Some(*identifier),
+            _ => None,
+        };
+        let mut after_import = false;
+        for (index, (offset, token)) in tokens.iter().enumerate() {
+            let Token::Identifier(identifier) = token else {
+                continue;
+            };
+            let previous = index
+                .checked_sub(1)
+                .and_then(|index| tokens.get(index))
+                .map(|(_, token)| token);
+            let next = tokens.get(index + 1).map(|(_, token)| token);
+            // `x is divisibleby(3)` and `x is not startingwith("a")` tests
+            let is_test = previous == Some(&Token::Identifier("is"))
+                || (previous == Some(&Token::Identifier("not"))
+                    && index
+                        .checked_sub(2)
+                        .and_then(|index| tokens.get(index))
+                        .map(|(_, token)| token)
+                        == Some(&Token::Identifier("is")));
+            if *identifier == "import" {
+                after_import = true;
+            }
+            let in_for_targets = first_identifier == Some("for")
+                && !tokens[..index]
+                    .iter()
+                    .any(|(_, token)| *token == Token::Identifier("in"));
+            let in_from_import = first_identifier == Some("from") && after_import;
+            match previous {
+                _ if is_test => {}
+                Some(Token::Symbol('|')) => add(
+                    &mut used_names,
+                    NameKind::Filter,
+                    identifier,
+                    tag_offset + offset,
+                ),
+                // `{% filter upper %}` blocks
+                Some(Token::Identifier("filter")) if index == 1 => add(
+                    &mut used_names,
+                    NameKind::Filter,
+                    identifier,
+                    tag_offset + offset,
+                ),
+                Some(Token::Identifier("macro" | "set" | "as" | "for")) => {
+                    defined.insert(*identifier);
+                }
+                // `{% for key, value in ... %}` and `{% from "..." import a, b %}`
+                Some(Token::Symbol(',')) if in_for_targets || in_from_import => {
+                    defined.insert(*identifier);
+                }
+                Some(Token::Identifier("import")) if in_from_import => {
+                    defined.insert(*identifier);
+                }
+                Some(Token::Symbol('.')) => {}
+                _ if next == Some(&Token::Symbol('('))
+                    && !KEYWORDS.contains(identifier)
+                    && !SPECIAL_CALLABLES.contains(identifier) =>
+                {
+                    calls.push((*identifier, tag_offset + offset));
+                }
+                _ => {}
+            }
+        }
+    }
+    for (identifier, offset) in calls {
+        if !defined.contains(identifier) {
+            add(&mut used_names, NameKind::Function, identifier, offset);
+        }
+    }
+    used_names.sort_by_key(|used_name| used_name.line);
+    used_names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn used(kind: NameKind, name: &str, line: usize) -> UsedName {
+        UsedName {
+            kind,
+            name: name.to_owned(),
+            line,
+        }
+    }
+
+    #[test]
+    fn filters_are_used() {
+        assert_eq!(
+            used_names(
+                "{{ story.name | upper }}\n{{- story.labels|map(attribute='name')|join(', ') }}"
+            ),
+            [
+                used(NameKind::Filter, "upper", 1),
+                used(NameKind::Filter, "map", 2),
+                used(NameKind::Filter, "join", 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn filter_blocks_are_used() {
+        assert_eq!(
+            used_names("{% filter upper %}release{% endfilter %}"),
+            [used(NameKind::Filter, "upper", 1)]
+        );
+    }
+
+    #[test]
+    fn functions_are_used() {
+        assert_eq!(
+            used_names("{{ today() }}\n{{ range(3) | length }}"),
+            [
+                used(NameKind::Function, "today", 1),
+                used(NameKind::Filter, "length", 2),
+                used(NameKind::Function, "range", 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn macros_are_defined() {
+        assert_eq!(
+            used_names("{% macro item(story) %}{{ story.name }}{% endmacro %}{{ item(story) }}"),
+            []
+        );
+    }
+
+    #[test]
+    fn imports_are_defined() {
+        assert_eq!(
+            used_names(
+                "{% from \"macros.md\" import item, epic as title %}\n\
+                 {% import \"macros.md\" as macros %}\n\
+                 {{ item(story) }}{{ title(epic) }}{{ macros.item(story) }}"
+            ),
+            []
+        );
+    }
+
+    #[test]
+    fn loop_variables_are_defined() {
+        assert_eq!(
+            used_names("{% for name, format in formats %}{{ format(name) }}{% endfor %}"),
+            []
+        );
+    }
+
+    #[test]
+    fn string_literals_are_skipped() {
+        assert_eq!(
+            used_names("{{ \"upper(x) | lower\" }}{{ 'it\\'s today()' | trim }}"),
+            [used(NameKind::Filter, "trim", 1)]
+        );
+    }
+
+    #[test]
+    fn tests_are_skipped() {
+        assert_eq!(
+            used_names(
+                "{% if loop.index is divisibleby(3) and name is not startingwith(\"a\") %}\
+                 {% endif %}"
+            ),
+            []
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Formatting of the dates rendered by the templates, in a given timezone and locale.
+use anyhow::{anyhow, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Locale, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Default format of the dates, `YYYY-MM-DD`
+const DEFAULT_FORMAT: &str = "%F";
+
+/// Timezone, locale and default format of the dates
+#[derive(Debug, Clone, PartialEq)]
+pub struct DateSettings {
+    pub timezone: Tz,
+    pub locale: Locale,
+    pub format: String,
+}
+
+impl Default for DateSettings {
+    fn default() -> Self {
+        Self {
+            timezone: Tz::UTC,
+            locale: Locale::POSIX,
+            format: DEFAULT_FORMAT.to_owned(),
+        }
+    }
+}
+
+impl DateSettings {
+    /// Settings overriding the defaults, if given
+    pub fn new(timezone: Option<&str>, locale: Option<&str>, format: Option<&str>) -> Result<Self> {
+        let defaults = Self::default();
+        Ok(Self {
+            timezone: timezone.map_or(Ok(defaults.timezone), parse_timezone)?,
+            locale: locale.map_or(Ok(defaults.locale), parse_locale)?,
+            format: format.map_or(Ok(defaults.format), parse_format)?,
+        })
+    }
+
+    /// Settings with the timezone, locale and format overridden by the arguments of a filter or
+    /// function, if given
+    pub fn with_overrides(
+        &self,
+        format: Option<&str>,
+        timezone: Option<&str>,
+        locale: Option<&str>,
+    ) -> Result<Self> {
+        Ok(Self {
+            timezone: timezone.map_or(Ok(self.timezone), parse_timezone)?,
+            locale: locale.map_or(Ok(self.locale), parse_locale)?,
+            format: format.map_or_else(|| Ok(self.format.clone()), parse_format)?,
+        })
+    }
+
+    /// Format a date and time in the timezone and locale of the settings
+    pub fn format<T: TimeZone>(&self, datetime: &DateTime<T>) -> String {
+        datetime
+            .with_timezone(&self.timezone)
+            .format_localized(&self.format, self.locale)
+            .to_string()
+    }
+
+    /// Format a date without time (e.g. a deadline set to a day), which is kept as-is whatever
+    /// the timezone
+    pub fn format_date(&self, date: NaiveDate) -> String {
+        let midnight = date.and_hms_opt(0, 0, 0).expect("midnight should be valid");
+        Utc.from_utc_datetime(&midnight)
+            .format_localized(&self.format, self.locale)
+            .to_string()
+    }
+
+    /// Format a date and time given as RFC 3339 (e.g. `2023-09-20T15:30:00Z`, as returned by
+    /// Shortcut), or a date given as `YYYY-MM-DD`
+    pub fn format_str(&self, value: &str) -> Result<String> {
+        if let Ok(datetime) = DateTime::<FixedOffset>::parse_from_rfc3339(value) {
+            return Ok(self.format(&datetime));
+        }
+        NaiveDate::parse_from_str(value, "%F")
+            .map(|date| self.format_date(date))
+            .map_err(|_| {
+                anyhow!(
+                    "expected a RFC 3339 date and time or a YYYY-MM-DD date, got {:?}",
+                    value
+                )
+            })
+    }
+
+    /// Format a Unix timestamp, in seconds
+    pub fn format_timestamp(&self, timestamp: i64) -> Result<String> {
+        Utc.timestamp_opt(timestamp, 0)
+            .single()
+            .map(|datetime| self.format(&datetime))
+            .ok_or_else(|| anyhow!("invalid timestamp {}", timestamp))
+    }
+}
+
+/// Check a strftime format, e.g. `%d %B %Y`, as an invalid one would make the formatting panic
+fn parse_format(format: &str) -> Result<String> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(anyhow!(
+            "invalid date format {:?}, expected a strftime format such as %d %B %Y",
+            format
+        ));
+    }
+    Ok(format.to_owned())
+}
+
+/// Parse an IANA timezone name, e.g. `Europe/Paris`
+fn parse_timezone(timezone: &str) -> Result<Tz> {
+    timezone.parse().map_err(|_| {
+        anyhow!(
+            "unknown timezone {:?}, expected an IANA name such as Europe/Paris",
+            timezone
+        )
+    })
+}
+
+/// Parse a locale name, e.g. `fr_FR` or `fr-FR`
+fn parse_locale(locale: &str) -> Result<Locale> {
+    Locale::try_from(locale.replace('-', "_").as_str()).map_err(|_| {
+        anyhow!(
+            "unknown locale {:?}, expected a name such as fr_FR or en_CA",
+            locale
+        )
+    })
+}
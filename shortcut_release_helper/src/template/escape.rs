@@ -0,0 +1,117 @@
+//! Escaping of the values rendered by the templates, according to the output format.
+use std::{ffi::OsStr, fmt::Write, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Error};
+use lazy_static::lazy_static;
+use minijinja::{escape_formatter, AutoEscape, ErrorKind, HtmlEscape, Output, State, Value};
+use regex::{Captures, Regex};
+use serde::Deserialize;
+
+use super::markdown::escape_mrkdwn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EscapeMode {
+    None,
+    /// HTML entities
+    Html,
+    /// Markdown special characters, except in plain URLs
+    Markdown,
+    /// Slack control characters (`&`, `<` and `>`)
+    Slack,
+}
+
+impl EscapeMode {
+    /// Escape mode matching the extension of a file (ignoring a `.jinja` or `.j2` template
+    /// extension), if any
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let path = match path.extension().and_then(OsStr::to_str) {
+            Some("jinja" | "j2") => path.with_extension(""),
+            _ => path.to_owned(),
+        };
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "html" | "htm" => Some(Self::Html),
+            "md" | "markdown" => Some(Self::Markdown),
+            _ => None,
+        }
+    }
+
+    pub fn from_auto_escape(auto_escape: AutoEscape) -> Self {
+        match auto_escape {
+            AutoEscape::Html => Self::Html,
+            AutoEscape::Custom("markdown") => Self::Markdown,
+            AutoEscape::Custom("slack") => Self::Slack,
+            _ => Self::None,
+        }
+    }
+
+    pub fn auto_escape(self) -> AutoEscape {
+        match self {
+            Self::None => AutoEscape::None,
+            Self::Html => AutoEscape::Html,
+            Self::Markdown => AutoEscape::Custom("markdown"),
+            Self::Slack => AutoEscape::Custom("slack"),
+        }
+    }
+
+    pub fn escape(self, text: &str) -> String {
+        lazy_static! {
+            static ref MARKDOWN_ESCAPE_RE: Regex =
+                Regex::new(r##"([!"#$%&'()*+,-./:;<=>?@\[\]^_`{|}~\\])"##)
+                    .expect("Markdown escape regex does not compile");
+            static ref URL_RE: Regex =
+                Regex::new(r"^https?://\S+$").expect("URL regex does not compile");
+        };
+        match self {
+            Self::None => text.to_owned(),
+            Self::Html => HtmlEscape(text).to_string(),
+            // Escaping a URL would prevent it from being recognized as a link
+            Self::Markdown if URL_RE.is_match(text) => text.to_owned(),
+            Self::Markdown => MARKDOWN_ESCAPE_RE
+                .replace_all(text, |caps: &Captures| format!(r"\{}", &caps[1]))
+                .to_string(),
+            Self::Slack => escape_mrkdwn(text),
+        }
+    }
+}
+
+impl FromStr for EscapeMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "html" => Ok(Self::Html),
+            "markdown" => Ok(Self::Markdown),
+            "slack" => Ok(Self::Slack),
+            other => Err(anyhow!(
+                "unknown escape mode {:?}, expected one of none, html, markdown, slack",
+                other
+            )),
+        }
+    }
+}
+
+/// Formatter of the rendered values, escaping strings not marked as safe according to the
+/// auto-escape mode of the template
+pub fn format_value(
+    out: &mut Output,
+    state: &State,
+    value: &Value,
+) -> Result<(), minijinja::Error> {
+    let escape_mode = match EscapeMode::from_auto_escape(state.auto_escape()) {
+        escape_mode @ (EscapeMode::Markdown | EscapeMode::Slack) => escape_mode,
+        _ => return escape_formatter(out, state, value),
+    };
+    let result = match value.as_str() {
+        Some(text) if !value.is_safe() => out.write_str(&escape_mode.escape(text)),
+        _ if value.is_undefined() => Ok(()),
+        _ => write!(out, "{}", value),
+    };
+    result.map_err(|_| {
+        minijinja::Error::new(
+            ErrorKind::WriteFailure,
+            "could not write the rendered value",
+        )
+    })
+}
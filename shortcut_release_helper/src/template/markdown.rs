@@ -0,0 +1,217 @@
+//! Conversions of Markdown text (e.g. story and epic descriptions) to other formats.
+use pulldown_cmark::{html, Event, Options, Parser, Tag};
+
+fn parser(markdown: &str) -> Parser<'_, '_> {
+    Parser::new_ext(
+        markdown,
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS,
+    )
+}
+
+/// Convert Markdown to HTML. Raw HTML, block or inline, is escaped and thus rendered as text, so
+/// that a description cannot inject markup (e.g. scripts) in the output.
+pub fn to_html(markdown: &str) -> String {
+    let mut output = String::new();
+    html::push_html(
+        &mut output,
+        parser(markdown).map(|event| match event {
+            Event::Html(html) => Event::Text(html),
+            event => event,
+        }),
+    );
+    output
+}
+
+/// Escape the control characters of Slack messages
+pub fn escape_mrkdwn(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Start a new line, unless the output is empty or already ends with one
+fn ensure_newline(output: &mut String) {
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+}
+
+/// Convert Markdown to Slack's `mrkdwn` format
+pub fn to_mrkdwn(markdown: &str) -> String {
+    let mut output = String::new();
+    // Next number of each open list, `None` for bullet lists
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut block_quotes = 0;
+    for event in parser(markdown) {
+        match event {
+            Event::Start(Tag::Heading(..)) => output.push('*'),
+            Event::End(Tag::Heading(..)) => output.push_str("*\n\n"),
+            Event::End(Tag::Paragraph) => {
+                output.push('\n');
+                if lists.is_empty() {
+                    output.push('\n');
+                }
+            }
+            Event::Start(Tag::BlockQuote) => {
+                block_quotes += 1;
+                output.push_str("> ");
+            }
+            Event::End(Tag::BlockQuote) => block_quotes -= 1,
+            Event::Start(Tag::CodeBlock(_)) => {
+                ensure_newline(&mut output);
+                output.push_str("```\n");
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                ensure_newline(&mut output);
+                output.push_str("```\n\n");
+            }
+            Event::Start(Tag::List(start)) => lists.push(start),
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    output.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                ensure_newline(&mut output);
+                output.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(number)) => {
+                        output.push_str(&format!("{}. ", number));
+                        *number += 1;
+                    }
+                    _ => output.push_str("• "),
+                }
+            }
+            Event::End(Tag::Item) => ensure_newline(&mut output),
+            Event::Start(Tag::Emphasis) | Event::End(Tag::Emphasis) => output.push('_'),
+            Event::Start(Tag::Strong) | Event::End(Tag::Strong) => output.push('*'),
+            Event::Start(Tag::Strikethrough) | Event::End(Tag::Strikethrough) => output.push('~'),
+            Event::Start(Tag::Link(_, url, _)) | Event::Start(Tag::Image(_, url, _)) => {
+                output.push('<');
+                output.push_str(&url);
+                output.push('|');
+            }
+            Event::End(Tag::Link(..)) | Event::End(Tag::Image(..)) => output.push('>'),
+            Event::Text(text) | Event::Html(text) => output.push_str(&escape_mrkdwn(&text)),
+            Event::Code(code) => {
+                output.push('`');
+                output.push_str(&escape_mrkdwn(&code));
+                output.push('`');
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                output.push('\n');
+                if block_quotes > 0 {
+                    output.push_str("> ");
+                }
+            }
+            Event::Rule => output.push_str("———\n\n"),
+            Event::TaskListMarker(checked) => {
+                output.push_str(if checked { "☑ " } else { "☐ " })
+            }
+            _ => {}
+        }
+    }
+    output.trim_end().to_owned()
+}
+
+/// Convert Markdown to plain text, keeping only the text of the elements
+pub fn strip(markdown: &str) -> String {
+    let mut output = String::new();
+    let mut lists = 0;
+    for event in parser(markdown) {
+        match event {
+            Event::Text(text) | Event::Code(text) => output.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => output.push('\n'),
+            Event::Start(Tag::Item) => ensure_newline(&mut output),
+            Event::End(Tag::Paragraph)
+            | Event::End(Tag::Heading(..))
+            | Event::End(Tag::CodeBlock(_)) => {
+                ensure_newline(&mut output);
+                output.push('\n');
+            }
+            Event::Start(Tag::List(_)) => lists += 1,
+            Event::End(Tag::List(_)) => {
+                lists -= 1;
+                ensure_newline(&mut output);
+                if lists == 0 {
+                    output.push('\n');
+                }
+            }
+            _ => {}
+        }
+    }
+    output.trim_end().to_owned()
+}
+
+/// Keep the first words of a text, followed by `end` if the text was truncated. Whitespace
+/// between the kept words is preserved.
+pub fn truncate_words(text: &str, count: usize, end: &str) -> String {
+    let mut words = 0;
+    let mut in_word = false;
+    for (index, c) in text.char_indices() {
+        if c.is_whitespace() {
+            in_word = false;
+        } else if !in_word {
+            in_word = true;
+            words += 1;
+            if words > count {
+                return format!("{}{}", text[..index].trim_end(), end);
+            }
+        }
+    }
+    text.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_is_converted_to_html() {
+        assert_eq!(
+            to_html("# Login\n\nFix the **login** [form](https://acme.com)"),
+            "<h1>Login</h1>\n<p>Fix the <strong>login</strong> \
+             <a href=\"https://acme.com\">form</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn raw_html_is_escaped() {
+        assert_eq!(
+            to_html("<script>alert(1)</script>\n\nFix <img src=x onerror=alert(1)>"),
+            "&lt;script&gt;alert(1)&lt;/script&gt;\n\
+             <p>Fix &lt;img src=x onerror=alert(1)&gt;</p>\n"
+        );
+    }
+
+    #[test]
+    fn markdown_is_converted_to_mrkdwn() {
+        assert_eq!(
+            to_mrkdwn(
+                "# Login\n\nFix the **login** [form](https://acme.com) & <b>page</b>\n\n\
+                 - [x] Done\n- Not `done`"
+            ),
+            "*Login*\n\nFix the *login* <https://acme.com|form> &amp; &lt;b&gt;page&lt;/b&gt;\n\n\
+             • ☑ Done\n• Not `done`"
+        );
+    }
+
+    #[test]
+    fn markdown_is_stripped() {
+        assert_eq!(
+            strip("# Login\n\nFix the **login** [form](https://acme.com)\n\n- First\n- `Second`"),
+            "Login\n\nFix the login form\n\nFirst\nSecond"
+        );
+    }
+
+    #[test]
+    fn words_are_truncated() {
+        assert_eq!(
+            truncate_words("Fix the  login form", 3, "..."),
+            "Fix the  login..."
+        );
+        assert_eq!(truncate_words("Fix the login ", 3, "..."), "Fix the login ");
+        assert_eq!(truncate_words("", 3, "..."), "");
+    }
+}
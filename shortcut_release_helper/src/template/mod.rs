@@ -1,31 +1,198 @@
+mod builtin;
+mod check;
+mod dates;
+mod escape;
+mod markdown;
 mod utils;
 
-use std::{fs, path::Path};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use chrono::offset::Utc;
-use lazy_static::lazy_static;
 use minijinja::{
-    value::{Value, ValueKind},
-    Environment, ErrorKind, State,
+    context, path_loader,
+    value::{Rest, Value, ValueKind},
+    Environment, ErrorKind, State, UndefinedBehavior,
 };
-use regex::{Captures, Regex};
+use serde::Serialize;
 
-use crate::Release;
+use crate::{
+    config::{AppConfig, EmojiConfig},
+    Release,
+};
+pub use builtin::builtin_template;
+use builtin::BUILTIN_PREFIX;
+use check::{NameKind, UsedName};
+use dates::DateSettings;
+pub use escape::EscapeMode;
 use utils::SeqIterator;
 
+/// Template path standing for the standard input
+const STDIN_PATH: &str = "-";
+
+/// Synthetic release rendered by `check-template`, with a few stories of each type, an epic,
+/// unparsed commits and next heads
+const SAMPLE_RELEASE: &str = include_str!("sample_release.json");
+
+/// Settings of the template environment, from the configuration
+#[derive(Debug, Clone, Default)]
+pub struct TemplateOptions {
+    /// Directory of the templates included, imported or extended by the templates, defaults to
+    /// the directory of each template
+    pub template_dir: Option<PathBuf>,
+    pub emoji: EmojiConfig,
+    /// Human-readable names of the story types, e.g. for section headings
+    pub story_type_labels: HashMap<String, String>,
+    /// Auto-escape mode, defaults to the mode of the template (according to its extension)
+    pub escape: Option<EscapeMode>,
+    /// Timezone, locale and default format of the dates
+    pub dates: DateSettings,
+}
+
+impl TemplateOptions {
+    /// Template settings of the configuration
+    pub fn new(config: &AppConfig) -> Result<Self> {
+        Ok(Self {
+            template_dir: config.template_dir.clone(),
+            emoji: config.emoji.clone(),
+            story_type_labels: config.story_type_labels.clone(),
+            escape: None,
+            dates: DateSettings::new(
+                config.dates.timezone.as_deref(),
+                config.dates.locale.as_deref(),
+                config.dates.format.as_deref(),
+            )
+            .map_err(|err| anyhow!("Invalid [dates] configuration: {}", err))?,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct FileTemplate<'a> {
     environment: Environment<'a>,
+    /// Name of the template to render in the environment
+    name: String,
 }
 
-const TEMPLATE_NAME: &str = "main";
+impl FileTemplate<'static> {
+    /// Load a template from a file, a built-in template if the path is `builtin:<name>`, or the
+    /// template read from the standard input if the path is `-`
+    pub fn load(template: &Path, options: &TemplateOptions) -> Result<Self> {
+        if template == Path::new(STDIN_PATH) {
+            return Self::from_stdin(options);
+        }
+        match template
+            .to_str()
+            .and_then(|template| template.strip_prefix(BUILTIN_PREFIX))
+        {
+            Some(name) => Self::builtin(name, options),
+            None => Self::from_file(template, options),
+        }
+    }
+
+    /// Read a template from the standard input. The templates it includes, imports or extends
+    /// are loaded from the template directory (defaults to the current directory).
+    fn from_stdin(options: &TemplateOptions) -> Result<Self> {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).map_err(|err| {
+            anyhow!(
+                "Could not read the template from the standard input: {}",
+                err
+            )
+        })?;
+        let name = "<stdin>".to_owned();
+        let mut environment =
+            Self::environment(options, options.escape.unwrap_or(EscapeMode::None));
+        environment.set_loader(path_loader(
+            options
+                .template_dir
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(".")),
+        ));
+        environment
+            .add_template_owned(name.clone(), source)
+            .map_err(template_error)?;
+        Ok(Self { environment, name })
+    }
+
+    fn builtin(name: &str, options: &TemplateOptions) -> Result<Self> {
+        let builtin_template = builtin_template(name)?;
+        let name = format!("{}{}", BUILTIN_PREFIX, builtin_template.name);
+        let mut environment =
+            Self::environment(options, options.escape.unwrap_or(builtin_template.escape));
+        environment
+            .add_template_owned(name.clone(), builtin_template.content)
+            .map_err(template_error)?;
+        Ok(Self { environment, name })
+    }
+
+    /// Load a template file, along with the templates it includes, imports or extends, from the
+    /// template directory (defaults to the directory of the template file). The latter are
+    /// referenced by their path relative to the template directory, e.g.
+    /// `{% import "macros/story.jinja" as story %}`.
+    fn from_file(template_file: &Path, options: &TemplateOptions) -> Result<Self> {
+        let template_dir = match options.template_dir.as_deref() {
+            Some(template_dir) => template_dir,
+            None => template_file
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new(".")),
+        };
+        // Both paths are made absolute and resolved, as either may be relative, or contain `.`
+        // or `..` components
+        let canonical_template_file = fs::canonicalize(template_file)
+            .map_err(|err| anyhow!("Could not read {}: {}", template_file.display(), err))?;
+        let canonical_template_dir = fs::canonicalize(template_dir).map_err(|err| {
+            anyhow!(
+                "Could not read the template directory {}: {}",
+                template_dir.display(),
+                err
+            )
+        })?;
+        let relative_path = canonical_template_file
+            .strip_prefix(&canonical_template_dir)
+            .map_err(|_| {
+                anyhow!(
+                    "Template {} is not in the template directory {}",
+                    template_file.display(),
+                    template_dir.display()
+                )
+            })?;
+        // Template names always use forward slashes, whatever the platform
+        let name = relative_path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(segment) => Some(segment.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let escape = options
+            .escape
+            .or_else(|| EscapeMode::from_path(template_file))
+            .unwrap_or(EscapeMode::None);
+        let mut environment = Self::environment(options, escape);
+        environment.set_loader(path_loader(canonical_template_dir));
+        // Load the template right away, to report syntax errors before any work is done
+        environment.get_template(&name).map_err(template_error)?;
+        Ok(Self { environment, name })
+    }
+}
 
 impl<'a> FileTemplate<'a> {
-    pub fn new(template_content: &'a str) -> Result<Self> {
+    /// Environment with the filters and functions available to templates
+    fn environment(options: &TemplateOptions, escape: EscapeMode) -> Environment<'a> {
         let mut environment = Environment::new();
-
-        environment.add_template(TEMPLATE_NAME, template_content)?;
+        let emoji = Arc::new(options.emoji.clone());
+        let story_type_labels = Arc::new(options.story_type_labels.clone());
+        let dates = Arc::new(options.dates.clone());
 
         environment.add_filter(
             "split_by_epic_stories_state",
@@ -33,39 +200,116 @@ impl<'a> FileTemplate<'a> {
         );
         environment.add_filter("split_by_label", Self::split_by_label);
         environment.add_filter("split_by_epic", Self::split_by_epic);
+        environment.add_filter("split_by_state", Self::split_by_state);
+        environment.add_filter("group_by_attr", Self::group_by_attr);
+        environment.add_filter("group_by_label_prefix", Self::group_by_label_prefix);
+        environment.add_filter("sort_by", Self::sort_by);
+        environment.add_filter("partition_by", Self::partition_by);
         environment.add_filter("has_label", Self::has_label);
-        environment.add_filter("story_emoji", Self::story_emoji);
+        environment.add_filter("story_emoji", {
+            let emoji = emoji.clone();
+            move |story: Value| Self::story_emoji(&emoji, story)
+        });
+        environment.add_filter("story_type_label", {
+            let story_type_labels = story_type_labels.clone();
+            move |story_type: Value| Self::story_type_label(&story_type_labels, story_type)
+        });
         environment.add_filter("indent", Self::indent);
         environment.add_filter("escape", Self::escape);
+        environment.add_filter("member_name", Self::member_name);
+        environment.add_filter("markdown_to_html", Self::markdown_to_html);
+        environment.add_filter("markdown_to_mrkdwn", Self::markdown_to_mrkdwn);
+        environment.add_filter("strip_markdown", Self::strip_markdown);
+        environment.add_filter("truncate_words", Self::truncate_words);
+        environment.add_filter("format_date", {
+            let dates = dates.clone();
+            move |v: Value,
+                  format: Option<String>,
+                  timezone: Option<String>,
+                  locale: Option<String>| {
+                Self::format_date(&dates, v, format, timezone, locale)
+            }
+        });
 
-        environment.add_function("today", Self::today);
-        environment.add_function("epic_emoji", Self::epic_emoji);
+        environment.add_function(
+            "today",
+            move |format: Option<String>, timezone: Option<String>, locale: Option<String>| {
+                Self::today(&dates, format, timezone, locale)
+            },
+        );
+        environment.add_function("epic_emoji", move |epic: Option<Value>| {
+            Self::epic_emoji(&emoji, epic)
+        });
+        environment.add_function("story_type_label", move |story_type: Value| {
+            Self::story_type_label(&story_type_labels, story_type)
+        });
+        environment.add_function("member_name", Self::member_name);
 
-        environment.set_auto_escape_callback(|_| minijinja::AutoEscape::None);
+        environment.set_auto_escape_callback(move |_| escape.auto_escape());
+        environment.set_formatter(escape::format_value);
 
-        Ok(Self { environment })
+        environment
     }
 
-    /// Escape Markdown characters - useful for epic and story titles
-    fn escape(_state: &State, v: Value) -> Result<Value, minijinja::Error> {
-        lazy_static! {
-            static ref MARKDOWN_ESCAPE_RE: Regex =
-                Regex::new(r##"([!"#$%&'()*+,-./:;<=>?@\[\]^_`{|}~\\])"##)
-                    .expect("Markdown escape regex does not compile");
+    /// Escape special characters according to the given mode (`html`, `markdown`, `slack` or
+    /// `none`), defaulting to the auto-escape mode of the template, or Markdown if the template
+    /// is not auto-escaped - useful for epic and story titles
+    fn escape(state: &State, v: Value, mode: Option<String>) -> Result<Value, minijinja::Error> {
+        let mode = match mode.as_deref() {
+            Some(mode) => mode.parse::<EscapeMode>().map_err(|err| {
+                minijinja::Error::new(ErrorKind::InvalidOperation, err.to_string())
+            })?,
+            None => match EscapeMode::from_auto_escape(state.auto_escape()) {
+                EscapeMode::None => EscapeMode::Markdown,
+                mode => mode,
+            },
         };
         let v = match v.kind() {
             ValueKind::String => {
                 let string = v.as_str().expect("should be a string");
-                let escaped_string = MARKDOWN_ESCAPE_RE
-                    .replace_all(string, |caps: &Captures| format!(r"\{}", &caps[1]))
-                    .to_string();
-                Value::from_safe_string(escaped_string)
+                Value::from_safe_string(mode.escape(string))
             }
             _ => v,
         };
         Ok(v)
     }
 
+    /// Convert Markdown text (e.g. a story description) to HTML
+    fn markdown_to_html(_state: &State, v: Option<String>) -> Result<Value, minijinja::Error> {
+        Ok(Value::from_safe_string(markdown::to_html(
+            v.as_deref().unwrap_or_default(),
+        )))
+    }
+
+    /// Convert Markdown text to Slack's `mrkdwn` format
+    fn markdown_to_mrkdwn(_state: &State, v: Option<String>) -> Result<Value, minijinja::Error> {
+        Ok(Value::from_safe_string(markdown::to_mrkdwn(
+            v.as_deref().unwrap_or_default(),
+        )))
+    }
+
+    /// Convert Markdown text to plain text
+    fn strip_markdown(_state: &State, v: Option<String>) -> Result<Value, minijinja::Error> {
+        Ok(Value::from(markdown::strip(
+            v.as_deref().unwrap_or_default(),
+        )))
+    }
+
+    /// Keep the first words of a text, followed by `end` (`…` by default) if the text was
+    /// truncated
+    fn truncate_words(
+        _state: &State,
+        v: Option<String>,
+        count: usize,
+        end: Option<String>,
+    ) -> Result<Value, minijinja::Error> {
+        Ok(Value::from(markdown::truncate_words(
+            v.as_deref().unwrap_or_default(),
+            count,
+            end.as_deref().unwrap_or("…"),
+        )))
+    }
+
     /// Indent multiline text by prefixing the platform's linebreak in the value by the amount of
     /// spaces indicated.
     fn indent(_state: &State, v: Value, amount: Value) -> Result<Value, minijinja::Error> {
@@ -178,35 +422,278 @@ impl<'a> FileTemplate<'a> {
         Ok(Value::from(vec![matched, unmatched]))
     }
 
-    fn story_emoji(_state: &State, story: Value) -> Result<Value, minijinja::Error> {
-        if !matches!(story.kind(), ValueKind::Map) {
+    /// Split stories according to their workflow state, matching either the state type
+    /// (`unstarted`, `started`, `done`) or the state name (e.g. `In Review`)
+    fn split_by_state(_state: &State, v: Value, state: Value) -> Result<Value, minijinja::Error> {
+        let state_name = if matches!(state.kind(), ValueKind::String) {
+            state.as_str().expect("Should be a string")
+        } else {
             return Err(minijinja::Error::new(
                 ErrorKind::InvalidOperation,
-                "expected an object",
+                "expected a string",
             ));
+        };
+        let (mut matched, mut unmatched) = (Vec::new(), Vec::new());
+        let stories_iter = SeqIterator::new(v)?;
+        for story in stories_iter {
+            let workflow_state = story.get_attr("workflow_state")?;
+            let is_matched = ["type", "name"].into_iter().any(|attr| {
+                workflow_state.get_attr(attr).map_or(false, |value| {
+                    value.as_str().map_or(false, |value| value == state_name)
+                })
+            });
+            if is_matched {
+                matched.push(story)
+            } else {
+                unmatched.push(story)
+            };
         }
-        const FEATURE_EMOJI: &str = ":sunny:";
-        const CHORE_EMOJI: &str = ":wrench:";
-        const BUG_EMOJI: &str = ":lady_beetle:";
-        if let Some(story_type) = story.get_attr("story_type")?.as_str() {
-            let emoji = match story_type {
-                "feature" => FEATURE_EMOJI,
-                "chore" => CHORE_EMOJI,
-                "bug" => BUG_EMOJI,
-                other => {
-                    return Err(minijinja::Error::new(
-                        ErrorKind::InvalidOperation,
-                        format!("Unknown story_type {}", other),
-                    ))
+        Ok(Value::from(vec![matched, unmatched]))
+    }
+
+    /// Look up an attribute by path, e.g. `workflow_state.name`, returning `none` if any part of
+    /// the path is missing
+    fn attr_path(value: &Value, path: &str) -> Result<Value, minijinja::Error> {
+        let mut value = value.clone();
+        for attr in path.split('.') {
+            if value.is_undefined() || value.is_none() {
+                break;
+            }
+            value = value.get_attr(attr)?;
+        }
+        Ok(if value.is_undefined() {
+            Value::from(())
+        } else {
+            value
+        })
+    }
+
+    /// Total order of the keys of the `group_by_*` and `sort_by` filters: `none` (or undefined)
+    /// last, then by kind (booleans, numbers, strings, lists, then other values), then by value,
+    /// values which cannot be compared being ordered by their string representation
+    fn cmp_keys(key: &Value, other_key: &Value) -> Ordering {
+        let kind_rank = |key: &Value| match key.kind() {
+            ValueKind::Bool => 0,
+            ValueKind::Number => 1,
+            ValueKind::String => 2,
+            ValueKind::Seq => 3,
+            ValueKind::Undefined | ValueKind::None => 5,
+            _ => 4,
+        };
+        kind_rank(key).cmp(&kind_rank(other_key)).then_with(|| {
+            key.partial_cmp(other_key)
+                .unwrap_or_else(|| key.to_string().cmp(&other_key.to_string()))
+        })
+    }
+
+    /// Build the `{key, items}` groups returned by the `group_by_*` filters, sorted by key, with
+    /// the items without a key last, under a `none` key
+    fn groups(mut groups: Vec<(Value, Vec<Value>)>, ungrouped: Vec<Value>) -> Value {
+        groups.sort_by(|(key, _), (other_key, _)| Self::cmp_keys(key, other_key));
+        if !ungrouped.is_empty() {
+            groups.push((Value::from(()), ungrouped));
+        }
+        Value::from(
+            groups
+                .into_iter()
+                .map(|(key, items)| context! { key, items })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Group stories or epics by the value of an attribute, e.g. `story_type` or
+    /// `workflow_state.name`
+    fn group_by_attr(_state: &State, v: Value, attr: String) -> Result<Value, minijinja::Error> {
+        let mut groups: Vec<(Value, Vec<Value>)> = Vec::new();
+        let mut ungrouped = Vec::new();
+        for item in SeqIterator::new(v)? {
+            let key = Self::attr_path(&item, &attr)?;
+            if key.is_none() {
+                ungrouped.push(item);
+            } else if let Some((_, items)) = groups.iter_mut().find(|(other, _)| *other == key) {
+                items.push(item);
+            } else {
+                groups.push((key, vec![item]));
+            }
+        }
+        Ok(Self::groups(groups, ungrouped))
+    }
+
+    /// Group stories or epics by their labels starting with a prefix, e.g. `area:` (or `area:*`),
+    /// the key of each group being the rest of the label name. An item with several matching
+    /// labels belongs to several groups.
+    fn group_by_label_prefix(
+        _state: &State,
+        v: Value,
+        prefix: String,
+    ) -> Result<Value, minijinja::Error> {
+        let prefix = prefix.trim_end_matches('*');
+        let mut groups: Vec<(Value, Vec<Value>)> = Vec::new();
+        let mut ungrouped = Vec::new();
+        for item in SeqIterator::new(v)? {
+            let mut grouped = false;
+            for label in SeqIterator::new(item.get_attr("labels")?)? {
+                let label_name = label.get_attr("name")?;
+                let Some(key) = label_name
+                    .as_str()
+                    .and_then(|name| name.strip_prefix(prefix))
+                    .map(|key| Value::from(key.trim()))
+                else {
+                    continue;
+                };
+                grouped = true;
+                if let Some((_, items)) = groups.iter_mut().find(|(other, _)| *other == key) {
+                    items.push(item.clone());
+                } else {
+                    groups.push((key, vec![item.clone()]));
                 }
+            }
+            if !grouped {
+                ungrouped.push(item);
+            }
+        }
+        Ok(Self::groups(groups, ungrouped))
+    }
+
+    /// Sort stories or epics by one or more attributes, e.g. `sort_by("epic_id", "-id")`, a `-`
+    /// prefix sorting by descending values. Items without the attribute are sorted last, in
+    /// either order. The sort is stable.
+    fn sort_by(_state: &State, v: Value, attrs: Rest<String>) -> Result<Value, minijinja::Error> {
+        if attrs.is_empty() {
+            return Err(minijinja::Error::new(
+                ErrorKind::MissingArgument,
+                "expected at least one attribute",
+            ));
+        }
+        let attrs = attrs
+            .iter()
+            .map(|attr| match attr.strip_prefix('-') {
+                Some(attr) => (attr, true),
+                None => (attr.as_str(), false),
+            })
+            .collect::<Vec<_>>();
+        let mut keyed_items = SeqIterator::new(v)?
+            .map(|item| {
+                let keys = attrs
+                    .iter()
+                    .map(|(attr, _)| Self::attr_path(&item, attr))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok((keys, item))
+            })
+            .collect::<Result<Vec<_>, minijinja::Error>>()?;
+        keyed_items.sort_by(|(keys, _), (other_keys, _)| {
+            keys.iter()
+                .zip(other_keys)
+                .zip(&attrs)
+                .map(|((key, other_key), (_, descending))| {
+                    let ordering = Self::cmp_keys(key, other_key);
+                    if *descending && !key.is_none() && !other_key.is_none() {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+        Ok(Value::from(
+            keyed_items
+                .into_iter()
+                .map(|(_, item)| item)
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    /// Split stories or epics according to an expression evaluated with the attributes of each
+    /// item as variables, e.g. `partition_by("story_type == 'bug' and estimate")`
+    fn partition_by(
+        state: &State,
+        v: Value,
+        expression: String,
+    ) -> Result<Value, minijinja::Error> {
+        let expression = state.env().compile_expression(&expression)?;
+        let (mut matched, mut unmatched) = (Vec::new(), Vec::new());
+        for item in SeqIterator::new(v)? {
+            if expression.eval(&item)?.is_true() {
+                matched.push(item)
+            } else {
+                unmatched.push(item)
             };
-            Ok(Value::from_safe_string(emoji.to_string()))
-        } else {
-            Err(minijinja::Error::new(
+        }
+        Ok(Value::from(vec![matched, unmatched]))
+    }
+
+    /// Emoji of a story: the emoji of its first label with one, else of its type (by default
+    /// :sunny: feature, :lady_beetle: bug or :wrench: chore), else the fallback emoji
+    fn story_emoji(emoji: &EmojiConfig, story: Value) -> Result<Value, minijinja::Error> {
+        if !matches!(story.kind(), ValueKind::Map) {
+            return Err(minijinja::Error::new(
                 ErrorKind::InvalidOperation,
-                "no story_type attribute",
-            ))
+                "expected an object",
+            ));
+        }
+        let labels = story.get_attr("labels")?;
+        if !emoji.labels.is_empty() && matches!(labels.kind(), ValueKind::Seq) {
+            for label in SeqIterator::new(labels)? {
+                let label_emoji = label
+                    .get_attr("name")?
+                    .as_str()
+                    .and_then(|name| emoji.labels.get(name));
+                if let Some(label_emoji) = label_emoji {
+                    return Ok(Value::from_safe_string(label_emoji.clone()));
+                }
+            }
         }
+        let story_type = story.get_attr("story_type")?;
+        let story_type = story_type.as_str().ok_or_else(|| {
+            minijinja::Error::new(ErrorKind::InvalidOperation, "no story_type attribute")
+        })?;
+        let story_emoji = emoji
+            .story_types
+            .get(story_type)
+            .map(String::as_str)
+            .or(match story_type {
+                "feature" => Some(":sunny:"),
+                "chore" => Some(":wrench:"),
+                "bug" => Some(":lady_beetle:"),
+                _ => None,
+            })
+            .or(emoji.fallback.as_deref())
+            .unwrap_or_default();
+        Ok(Value::from_safe_string(story_emoji.to_owned()))
+    }
+
+    /// Human-readable name of a story type (or of the type of a story), for section headings
+    fn story_type_label(
+        story_type_labels: &HashMap<String, String>,
+        story_type: Value,
+    ) -> Result<Value, minijinja::Error> {
+        let story_type = match story_type.kind() {
+            ValueKind::Map => story_type.get_attr("story_type")?,
+            _ => story_type,
+        };
+        let story_type = story_type.as_str().ok_or_else(|| {
+            minijinja::Error::new(
+                ErrorKind::InvalidOperation,
+                "expected a story type or a story",
+            )
+        })?;
+        let label = match story_type_labels.get(story_type) {
+            Some(label) => label.clone(),
+            None => match story_type {
+                "feature" => "Features".to_owned(),
+                "bug" => "Bug fixes".to_owned(),
+                "chore" => "Chores".to_owned(),
+                other => {
+                    let mut chars = other.chars();
+                    chars
+                        .next()
+                        .map(|first| first.to_uppercase().chain(chars).collect())
+                        .unwrap_or_default()
+                }
+            },
+        };
+        Ok(Value::from(label))
     }
 
     fn has_label(
@@ -232,25 +719,201 @@ impl<'a> FileTemplate<'a> {
         Ok(Value::from(has_label))
     }
 
+    /// Resolve a member id (UUID) to the member's name, using the `members` map of the template
+    /// context. Unknown ids are returned as-is.
+    fn member_name(state: &State, member_id: Value) -> Result<Value, minijinja::Error> {
+        if !matches!(member_id.kind(), ValueKind::String) {
+            return Err(minijinja::Error::new(
+                ErrorKind::InvalidOperation,
+                "expected a string",
+            ));
+        }
+        let profile = state
+            .lookup("members")
+            .and_then(|members| members.get_item(&member_id).ok())
+            .filter(|member| !member.is_undefined())
+            .and_then(|member| member.get_attr("profile").ok());
+        let name = profile.and_then(|profile| {
+            ["name", "mention_name"].into_iter().find_map(|attr| {
+                profile
+                    .get_attr(attr)
+                    .ok()
+                    .and_then(|name| name.as_str().map(ToOwned::to_owned))
+            })
+        });
+        Ok(name.map_or(member_id, Value::from))
+    }
+
     /// Helper returning today's date, formatted according to a format string following
-    /// [`chrono::format::strftime`] (if present), otherwise defaults to `YYYY-MM-DD`.
-    fn today(_state: &State, fmt: Option<String>) -> Result<Value, minijinja::Error> {
-        Ok(Value::from_safe_string(
-            Utc::now()
-                .format(fmt.as_deref().unwrap_or("%F"))
-                .to_string(),
-        ))
+    /// [`chrono::format::strftime`] (if present), otherwise defaults to the `[dates]` format
+    /// (`YYYY-MM-DD` by default), in the given timezone and locale (if present), otherwise the
+    /// `[dates]` ones
+    fn today(
+        dates: &DateSettings,
+        format: Option<String>,
+        timezone: Option<String>,
+        locale: Option<String>,
+    ) -> Result<Value, minijinja::Error> {
+        let dates = Self::date_settings(dates, format, timezone, locale)?;
+        Ok(Value::from_safe_string(dates.format(&Utc::now())))
     }
 
-    fn epic_emoji(_state: &State) -> Result<Value, minijinja::Error> {
+    /// Format a date (e.g. `story.completed_at`, `epic.deadline` or `commit.committed_at`) like
+    /// [`today`](Self::today). Dates given as RFC 3339 strings or Unix timestamps are converted
+    /// to the timezone, while `YYYY-MM-DD` dates are kept as-is. `none` is returned unchanged.
+    fn format_date(
+        dates: &DateSettings,
+        v: Value,
+        format: Option<String>,
+        timezone: Option<String>,
+        locale: Option<String>,
+    ) -> Result<Value, minijinja::Error> {
+        if v.is_none() || v.is_undefined() {
+            return Ok(v);
+        }
+        let dates = Self::date_settings(dates, format, timezone, locale)?;
+        let formatted = match v.as_str() {
+            Some(date) => dates.format_str(date),
+            None => match i64::try_from(v) {
+                Ok(timestamp) => dates.format_timestamp(timestamp),
+                Err(_) => Err(anyhow!("expected a date string or a timestamp")),
+            },
+        };
+        formatted
+            .map(Value::from_safe_string)
+            .map_err(|err| minijinja::Error::new(ErrorKind::InvalidOperation, err.to_string()))
+    }
+
+    /// Date settings overridden by the arguments of a filter or function
+    fn date_settings(
+        dates: &DateSettings,
+        format: Option<String>,
+        timezone: Option<String>,
+        locale: Option<String>,
+    ) -> Result<DateSettings, minijinja::Error> {
+        dates
+            .with_overrides(format.as_deref(), timezone.as_deref(), locale.as_deref())
+            .map_err(|err| minijinja::Error::new(ErrorKind::InvalidOperation, err.to_string()))
+    }
+
+    /// Emoji of an epic, according to its state (if given), else the epic emoji (by default
+    /// :checkered_flag:)
+    fn epic_emoji(emoji: &EmojiConfig, epic: Option<Value>) -> Result<Value, minijinja::Error> {
         const EPIC_EMOJI: &str = ":checkered_flag:";
-        Ok(Value::from_safe_string(EPIC_EMOJI.to_string()))
+        let state = epic.map(|epic| epic.get_attr("state")).transpose()?;
+        let state_emoji = state
+            .as_ref()
+            .and_then(|state| state.as_str())
+            .and_then(|state| emoji.epic_states.get(state));
+        let epic_emoji = state_emoji
+            .or(emoji.epic.as_ref())
+            .map_or(EPIC_EMOJI, String::as_str);
+        Ok(Value::from_safe_string(epic_emoji.to_string()))
+    }
+
+    pub fn render(&self, release: &Release) -> Result<String> {
+        self.render_context(release)
     }
 
-    pub fn render_to_file(&self, release: &Release, output_file: &Path) -> Result<()> {
-        let template = self.environment.get_template(TEMPLATE_NAME)?;
-        let file_content = template.render(release)?;
-        fs::write(output_file, &file_content)?;
-        Ok(())
+    fn render_context<S: Serialize>(&self, context: S) -> Result<String> {
+        let template = self
+            .environment
+            .get_template(&self.name)
+            .map_err(template_error)?;
+        template.render(context).map_err(template_error)
+    }
+
+    /// Check the template without any git or Shortcut access: report the unknown filters and
+    /// functions it uses, then render it against a synthetic release, failing on any use of an
+    /// undefined variable. Returns the rendered synthetic release.
+    pub fn check(mut self) -> Result<String> {
+        let template = self
+            .environment
+            .get_template(&self.name)
+            .map_err(template_error)?;
+        let unknown_names = check::used_names(template.source())
+            .into_iter()
+            .filter(|used_name| !self.is_known(used_name))
+            .map(|used_name| used_name.to_string())
+            .collect::<Vec<_>>();
+        if !unknown_names.is_empty() {
+            bail!("{}", unknown_names.join("\n"));
+        }
+        self.environment
+            .set_undefined_behavior(UndefinedBehavior::Strict);
+        let sample_release: serde_json::Value = serde_json::from_str(SAMPLE_RELEASE)?;
+        self.render_context(sample_release)
+    }
+
+    /// Whether the filter or function is known to the environment
+    fn is_known(&self, used_name: &UsedName) -> bool {
+        match used_name.kind {
+            NameKind::Filter => !matches!(
+                self.environment
+                    .render_str(&format!("{{{{ none | {} }}}}", used_name.name), ()),
+                Err(err) if err.kind() == ErrorKind::UnknownFilter
+            ),
+            NameKind::Function => self
+                .environment
+                .render_str(&format!("{{{{ {} is defined }}}}", used_name.name), ())
+                .map_or(false, |defined| defined == "true"),
+        }
+    }
+}
+
+/// Convert a template error, including the template name, line and source excerpt of the error
+/// and of the errors causing it (e.g. in an included template)
+fn template_error(err: minijinja::Error) -> anyhow::Error {
+    let mut message = err.to_string();
+    push_debug_info(&mut message, &err);
+    let mut source = std::error::Error::source(&err);
+    while let Some(err) = source {
+        message.push_str(&format!("\ncaused by: {}", err));
+        if let Some(err) = err.downcast_ref::<minijinja::Error>() {
+            push_debug_info(&mut message, err);
+        }
+        source = err.source();
+    }
+    anyhow!(message)
+}
+
+fn push_debug_info(message: &mut String, err: &minijinja::Error) {
+    let debug_info = err.display_debug_info().to_string();
+    if !debug_info.is_empty() {
+        message.push('\n');
+        message.push_str(&debug_info);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_sorted_by_kind_then_value_with_none_last() {
+        let mut keys = vec![
+            Value::from(()),
+            Value::from("b"),
+            Value::from(2),
+            Value::from(vec![1]),
+            Value::from("a"),
+            Value::from(1.5),
+            Value::from(true),
+        ];
+
+        keys.sort_by(FileTemplate::cmp_keys);
+
+        assert_eq!(
+            keys,
+            [
+                Value::from(true),
+                Value::from(1.5),
+                Value::from(2),
+                Value::from("a"),
+                Value::from("b"),
+                Value::from(vec![1]),
+                Value::from(()),
+            ]
+        );
     }
 }
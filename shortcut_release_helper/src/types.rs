@@ -1,6 +1,14 @@
-use std::{collections::HashMap, path::PathBuf, string::ToString};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    str::FromStr,
+    string::ToString,
+};
 
+use anyhow::anyhow;
+use chrono::{DateTime, FixedOffset};
 use git2::Oid as GitOid;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize, Serializer};
 
 /// Name of the Shortcut instance
@@ -14,7 +22,7 @@ impl ShortcutApiKey {
 }
 
 /// Name of the repository, must be unique
-#[derive(Debug, PartialEq, Eq, Hash, Clone, AsRef, Deserialize, Display, Serialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, AsRef, Deserialize, Display, Serialize, JsonSchema)]
 #[serde(transparent)]
 pub struct RepositoryName(String);
 
@@ -39,24 +47,64 @@ pub struct RepositoryLocation(PathBuf);
 #[serde(transparent)]
 pub struct RepositoryReference(String);
 
+/// A custom field value, with both the field and the value referenced by name
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+pub struct CustomFieldValue {
+    pub name: String,
+    pub value: String,
+}
+
+impl CustomFieldValue {
+    /// Whether the custom field values of a story, by field name, contain this value. Field
+    /// names and values are compared case-insensitively.
+    pub fn is_in(&self, story_values: &BTreeMap<String, String>) -> bool {
+        story_values.iter().any(|(name, value)| {
+            name.eq_ignore_ascii_case(&self.name) && value.eq_ignore_ascii_case(&self.value)
+        })
+    }
+}
+
+impl FromStr for CustomFieldValue {
+    type Err = anyhow::Error;
+
+    /// Parse a `<field name>=<value>` string
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected <field name>=<value>, got {:?}", s))?;
+        Ok(Self {
+            name: name.trim().to_owned(),
+            value: value.trim().to_owned(),
+        })
+    }
+}
+
 fn serialize_oid<S: Serializer>(oid: &GitOid, serializer: S) -> Result<S::Ok, S::Error> {
     serializer.serialize_str(&oid.to_string())
 }
 
 /// Head commit of a branch. May or may not have been released
-#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, JsonSchema)]
 pub struct HeadCommit {
     #[serde(serialize_with = "serialize_oid")]
+    #[schemars(with = "String")]
     pub id: GitOid,
     pub message: Option<String>,
+    /// Commit time, with the UTC offset of the committer
+    #[schemars(with = "String")]
+    pub committed_at: DateTime<FixedOffset>,
 }
 
 /// Commit only present in `next_branch`.
-#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, JsonSchema)]
 pub struct UnreleasedCommit {
     #[serde(serialize_with = "serialize_oid")]
+    #[schemars(with = "String")]
     pub id: GitOid,
     pub message: Option<String>,
+    /// Commit time, with the UTC offset of the committer
+    #[schemars(with = "String")]
+    pub committed_at: DateTime<FixedOffset>,
 }
 
 /// A repository name -> unreleased commits mapping